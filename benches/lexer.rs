@@ -0,0 +1,71 @@
+//! Lexer and parser throughput on a large generated script
+//!
+//! Run with `cargo bench`. Each benchmark reports the best of several runs,
+//! in megabytes of source per second
+extern crate shard;
+
+use std::time::{Duration, Instant};
+use shard::syntax::lexer::Lexer;
+use shard::syntax::parse_script;
+
+/// Number of times each benchmark is repeated
+const RUNS: usize = 10;
+
+/// Build a script resembling a SQL dump: a table definition followed by
+/// many multi-row inserts and some queries
+fn script(rows: usize) -> String {
+    let mut s = String::from(
+        "CREATE TABLE IF NOT EXISTS users (
+            id integer not null serial,
+            name text default 'anonymous',
+            score float,
+            avatar blob
+        );\n",
+    );
+    for i in 0..rows / 10 {
+        s.push_str("INSERT INTO users (id, name, score) VALUES ");
+        for j in 0..10 {
+            let id = i * 10 + j;
+            if j > 0 {
+                s.push_str(", ");
+            }
+            s.push_str(&format!("({}, 'user''s name {}', {}.5)", id, id, id % 100));
+        }
+        s.push_str(";\n");
+        s.push_str(&format!(
+            "SELECT id, name FROM users WHERE score >= {} AND name <> 'x' ORDER BY id DESC; -- {}\n",
+            i % 100,
+            i
+        ));
+    }
+    s
+}
+
+fn bench<F: FnMut() -> usize>(name: &str, bytes: usize, mut f: F) {
+    let mut best = Duration::from_secs(u64::MAX);
+    let mut count = 0;
+    for _ in 0..RUNS {
+        let start = Instant::now();
+        count = f();
+        best = best.min(start.elapsed());
+    }
+    let secs = best.as_secs() as f64 + f64::from(best.subsec_nanos()) / 1e9;
+    println!(
+        "{:<24} {:>10} items {:>10.2} ms {:>10.1} MB/s",
+        name,
+        count,
+        secs * 1e3,
+        bytes as f64 / secs / 1e6
+    );
+}
+
+fn main() {
+    let s = script(200_000);
+    println!("script: {} bytes", s.len());
+
+    bench("lex borrowed", s.len(), || Lexer::tokens(&s).count());
+    bench("lex from reader", s.len(), || {
+        Lexer::from_reader(s.as_bytes()).count()
+    });
+    bench("lex and parse", s.len(), || parse_script(&s).unwrap().len());
+}
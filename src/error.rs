@@ -0,0 +1,342 @@
+//! Errors reported while lexing, parsing and executing queries
+use std::error;
+use std::fmt;
+use std::result;
+use syntax::token::{Placeholder, Span, Token};
+
+pub type Result<T> = result::Result<T, Error>;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    /// A character that cannot begin or continue any token
+    IllegalCharacter {
+        found: char,
+        expected: String,
+        span: Span,
+    },
+    /// A literal that is still open at the end of input
+    UnterminatedLiteral { span: Span },
+    /// A block comment that is still open at the end of input
+    UnterminatedComment { span: Span },
+    /// A number that does not follow the numeric literal grammar
+    MalformedNumber { literal: String, span: Span },
+    /// A number that is too large to be represented
+    NumberOverflow { literal: String, span: Span },
+    /// The parser found a token other than one of those it expected
+    UnexpectedToken {
+        expected: Vec<String>,
+        found: Token<'static>,
+        span: Span,
+    },
+    /// The parser ran out of tokens while expecting more
+    UnexpectedEof { expected: Vec<String>, span: Span },
+    /// A column definition gives the same constraint more than once
+    DuplicateConstraint { constraint: String, span: Span },
+    /// Input could not be read, or was not valid UTF-8
+    Io { message: String, span: Span },
+    /// A numbered placeholder that is not a number from 1, such as `$0` or
+    /// `$1a`
+    InvalidPlaceholder { literal: String, span: Span },
+    /// No value was given for a placeholder when binding parameters
+    UnboundPlaceholder {
+        placeholder: Placeholder<'static>,
+        span: Span,
+    },
+    /// A NaN or infinite float was bound to a placeholder. There is no
+    /// literal for such a value
+    NonFiniteParam {
+        placeholder: Placeholder<'static>,
+        value: f64,
+        span: Span,
+    },
+    /// A statement refers to a table that does not exist
+    UnknownTable { name: String, span: Span },
+    /// An expression refers to a column that does not exist
+    UnknownColumn { name: String, span: Span },
+    /// A table is created with the name of one that already exists
+    TableExists { name: String, span: Span },
+    /// A statement tries to drop a table that the database maintains itself
+    SystemTable { name: String, span: Span },
+    /// A table definition has two columns with the same name
+    DuplicateColumn { name: String, span: Span },
+    /// A value had a type other than the one required
+    TypeMismatch {
+        expected: String,
+        found: String,
+        span: Span,
+    },
+    /// An operator was applied to values of types it does not support
+    InvalidOperands {
+        op: String,
+        operands: Vec<String>,
+        span: Span,
+    },
+    /// The result of integer arithmetic does not fit in 64 bits
+    IntegerOverflow { op: String, span: Span },
+    /// A row has fewer values than its table has columns, so a column
+    /// refers past its end. `column` counts from 1
+    ShortRow { column: usize, span: Span },
+    /// A row of an `INSERT` has a different number of values than columns
+    ValueCount {
+        expected: usize,
+        found: usize,
+        span: Span,
+    },
+    /// The rows of `shard_master` do not describe a valid set of tables
+    CorruptCatalog { message: String },
+    /// A database file could not be read or written
+    Storage { message: String },
+}
+
+impl Error {
+    /// Location in the source where the error occurred. Errors that do not
+    /// come from any source are given the default span
+    pub fn span(&self) -> Span {
+        match *self {
+            Error::IllegalCharacter { span, .. }
+            | Error::UnterminatedLiteral { span }
+            | Error::UnterminatedComment { span }
+            | Error::MalformedNumber { span, .. }
+            | Error::NumberOverflow { span, .. }
+            | Error::UnexpectedToken { span, .. }
+            | Error::UnexpectedEof { span, .. }
+            | Error::DuplicateConstraint { span, .. }
+            | Error::Io { span, .. }
+            | Error::InvalidPlaceholder { span, .. }
+            | Error::UnboundPlaceholder { span, .. }
+            | Error::NonFiniteParam { span, .. }
+            | Error::UnknownTable { span, .. }
+            | Error::UnknownColumn { span, .. }
+            | Error::TableExists { span, .. }
+            | Error::SystemTable { span, .. }
+            | Error::DuplicateColumn { span, .. }
+            | Error::TypeMismatch { span, .. }
+            | Error::InvalidOperands { span, .. }
+            | Error::IntegerOverflow { span, .. }
+            | Error::ShortRow { span, .. }
+            | Error::ValueCount { span, .. } => span,
+            Error::CorruptCatalog { .. } | Error::Storage { .. } => Span::default(),
+        }
+    }
+}
+
+/// Join a set of expected items into `a, b or c`
+fn one_of(expected: &[String]) -> String {
+    match expected.split_last() {
+        None => "more input".into(),
+        Some((last, [])) => last.clone(),
+        Some((last, rest)) => format!("{} or {}", rest.join(", "), last),
+    }
+}
+
+impl Error {
+    /// Short description of the error, without its location
+    pub fn message(&self) -> String {
+        match *self {
+            Error::IllegalCharacter { found, .. } => format!("illegal character `{}`", found),
+            Error::UnterminatedLiteral { .. } => "unterminated literal".into(),
+            Error::UnterminatedComment { .. } => "unterminated block comment".into(),
+            Error::MalformedNumber { ref literal, .. } => {
+                format!("malformed number literal `{}`", literal)
+            }
+            Error::NumberOverflow { ref literal, .. } => {
+                format!("number literal `{}` is out of range", literal)
+            }
+            Error::UnexpectedToken {
+                ref expected,
+                ref found,
+                ..
+            } => format!("expected {}, found `{}`", one_of(expected), found),
+            Error::UnexpectedEof { ref expected, .. } => {
+                format!("expected {}, found end of input", one_of(expected))
+            }
+            Error::DuplicateConstraint { ref constraint, .. } => {
+                format!("duplicate `{}` constraint", constraint)
+            }
+            Error::Io { ref message, .. } => format!("could not read input: {}", message),
+            Error::InvalidPlaceholder { ref literal, .. } => {
+                format!("invalid placeholder `{}`", literal)
+            }
+            Error::UnboundPlaceholder { ref placeholder, .. } => {
+                format!("no value bound to placeholder `{}`", placeholder)
+            }
+            Error::NonFiniteParam {
+                ref placeholder,
+                value,
+                ..
+            } => format!("cannot bind `{}` to placeholder `{}`", value, placeholder),
+            Error::UnknownTable { ref name, .. } => format!("no such table `{}`", name),
+            Error::UnknownColumn { ref name, .. } => format!("no such column `{}`", name),
+            Error::TableExists { ref name, .. } => format!("table `{}` already exists", name),
+            Error::SystemTable { ref name, .. } => format!("cannot drop system table `{}`", name),
+            Error::DuplicateColumn { ref name, .. } => format!("duplicate column `{}`", name),
+            Error::TypeMismatch {
+                ref expected,
+                ref found,
+                ..
+            } => format!("expected {}, found {}", expected, found),
+            Error::InvalidOperands {
+                ref op,
+                ref operands,
+                ..
+            } => format!("cannot apply `{}` to {}", op, operands.join(" and ")),
+            Error::IntegerOverflow { ref op, .. } => format!("integer overflow in `{}`", op),
+            Error::ShortRow { column, .. } => format!("row has no value for column {}", column),
+            Error::ValueCount {
+                expected, found, ..
+            } => format!("expected {} values, found {}", expected, found),
+            Error::CorruptCatalog { ref message } => format!("corrupt catalog: {}", message),
+            Error::Storage { ref message } => format!("database file error: {}", message),
+        }
+    }
+
+    /// Label placed under the offending span when rendering
+    fn label(&self) -> String {
+        match *self {
+            Error::IllegalCharacter { ref expected, .. } => format!("expected {}", expected),
+            Error::UnterminatedLiteral { .. } => "literal is never closed".into(),
+            Error::UnterminatedComment { .. } => "comment is never closed".into(),
+            Error::MalformedNumber { .. } => "not a valid number".into(),
+            Error::NumberOverflow { .. } => "does not fit in 64 bits".into(),
+            Error::UnexpectedToken { ref expected, .. }
+            | Error::UnexpectedEof { ref expected, .. } => {
+                format!("expected {}", one_of(expected))
+            }
+            Error::DuplicateConstraint { .. } => "constraint is already given".into(),
+            Error::Io { .. } => "input stops here".into(),
+            Error::InvalidPlaceholder { .. } => "expected `$` and a number from 1".into(),
+            Error::UnboundPlaceholder { .. } => "placeholder is never bound".into(),
+            Error::NonFiniteParam { .. } => "only finite floats can be bound".into(),
+            Error::UnknownTable { .. } => "table not found".into(),
+            Error::UnknownColumn { .. } => "column not found".into(),
+            Error::TableExists { .. } => "table already exists".into(),
+            Error::SystemTable { .. } => "this table is maintained by the database".into(),
+            Error::DuplicateColumn { .. } => "column is already defined".into(),
+            Error::TypeMismatch { ref found, .. } => format!("this is {}", found),
+            Error::InvalidOperands { .. } => "invalid operands".into(),
+            Error::IntegerOverflow { .. } => "result does not fit in 64 bits".into(),
+            Error::ShortRow { .. } => "row is too short for this column".into(),
+            Error::ValueCount { .. } => "wrong number of values".into(),
+            Error::CorruptCatalog { .. } => "while reading the catalog".into(),
+            Error::Storage { .. } => "while accessing the database file".into(),
+        }
+    }
+
+    /// Render the error along with the line of `source` it occurred on,
+    /// underlining the offending span:
+    ///
+    /// ```text
+    /// error: expected identifier, found `WHERE`
+    ///  --> 2:8
+    ///   |
+    /// 2 |   from where
+    ///   |        ^^^^^ expected identifier
+    /// ```
+    pub fn render(&self, source: &str) -> String {
+        let span = self.span();
+        let text = source.lines().nth(span.line.saturating_sub(1)).unwrap_or("");
+
+        // Reproduce tabs in the padding so the carets stay aligned
+        let padding: String = text.chars()
+            .take(span.column.saturating_sub(1))
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+        // Only the first line of a multi-line span is underlined
+        let width = source
+            .get(span.start..span.end)
+            .and_then(|s| s.lines().next())
+            .map(|s| s.chars().count())
+            .unwrap_or(0)
+            .max(1);
+
+        let line = span.line.to_string();
+        let gutter = " ".repeat(line.len());
+        format!(
+            "error: {}\n{}--> {}:{}\n{} |\n{} | {}\n{} | {}{} {}\n",
+            self.message(),
+            gutter,
+            span.line,
+            span.column,
+            gutter,
+            line,
+            text,
+            gutter,
+            padding,
+            "^".repeat(width),
+            self.label()
+        )
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let span = self.span();
+        write!(
+            f,
+            "{} on line {}, column {}",
+            self.message(),
+            span.line,
+            span.column
+        )
+    }
+}
+
+impl error::Error for Error {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn display() {
+        let err = Error::UnexpectedToken {
+            expected: vec!["`FROM`".into()],
+            found: Token::WHERE,
+            span: Span::new(9, 14, 1, 10),
+        };
+        assert_eq!(
+            err.to_string(),
+            "expected `FROM`, found `WHERE` on line 1, column 10"
+        );
+
+        let err = Error::UnexpectedEof {
+            expected: vec!["`,`".into(), "`)`".into(), "expression".into()],
+            span: Span::new(20, 20, 2, 4),
+        };
+        assert_eq!(
+            err.to_string(),
+            "expected `,`, `)` or expression, found end of input on line 2, column 4"
+        );
+    }
+
+    #[test]
+    fn render() {
+        let source = "select *\n  from where";
+        let err = Error::UnexpectedToken {
+            expected: vec!["identifier".into()],
+            found: Token::WHERE,
+            span: Span::new(16, 21, 2, 8),
+        };
+        assert_eq!(
+            err.render(source),
+            "error: expected identifier, found `WHERE`\n \
+             --> 2:8\n  \
+             |\n\
+             2 |   from where\n  \
+             |        ^^^^^ expected identifier\n"
+        );
+    }
+
+    #[test]
+    fn render_end_of_input() {
+        let source = "select * from";
+        let err = Error::UnexpectedEof {
+            expected: vec!["identifier".into()],
+            span: Span::new(13, 13, 1, 14),
+        };
+        let rendered = err.render(source);
+        let lines: Vec<&str> = rendered.lines().collect();
+        assert_eq!(lines[3], "1 | select * from");
+        assert_eq!(lines[4], "  |              ^ expected identifier");
+    }
+}
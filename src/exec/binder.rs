@@ -0,0 +1,471 @@
+//! Semantic analysis of statements against the catalog
+//!
+//! The binder sits between the parser and the executor. It resolves every
+//! table and column name against the catalog, expands `*` into the columns
+//! it stands for, and gives every expression a type, so that a statement
+//! which would fail for those reasons is rejected without being run.
+//!
+//! An expression has the type of the values it produces, or no type if it
+//! is always `NULL`. Operators are typed by applying them to sample values
+//! of their operand types, so the binder accepts exactly those operands
+//! that evaluation does
+use error::{Error, Result};
+use syntax::ast::columns::Column;
+use syntax::ast::expr::{BinaryOp, Expr, ExprKind, UnaryOp};
+use syntax::ast::insert::Insert;
+use syntax::ast::select::Select;
+use syntax::ast::statement::Statement;
+use syntax::token::{Placeholder, Span, Token};
+use super::catalog::{Catalog, TableSchema};
+use super::value::{DataType, Value};
+
+/// An expression with its names resolved, and the type of its values
+#[derive(Debug, Clone, PartialEq)]
+pub struct BoundExpr {
+    pub kind: BoundExprKind,
+    /// `None` if the expression is always `NULL`
+    pub ty: Option<DataType>,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum BoundExprKind {
+    Literal(Value),
+    /// Position of a column in the scanned table
+    Column(usize),
+    Unary(UnaryOp, Box<BoundExpr>),
+    Binary(Box<BoundExpr>, BinaryOp, Box<BoundExpr>),
+    /// A placeholder left unbound, which has no known type
+    Placeholder(Placeholder<'static>),
+}
+
+/// A result column of a query
+#[derive(Debug, Clone, PartialEq)]
+pub struct BoundColumn {
+    pub name: String,
+    pub expr: BoundExpr,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BoundOrderBy {
+    pub expr: BoundExpr,
+    pub descending: bool,
+}
+
+/// A `SELECT` ready to be executed
+#[derive(Debug, Clone, PartialEq)]
+pub struct BoundSelect {
+    pub table: String,
+    /// Location of the table name in the query
+    pub table_span: Span,
+    /// Result columns, with wildcards expanded
+    pub columns: Vec<BoundColumn>,
+    pub predicate: Option<BoundExpr>,
+    pub order_by: Vec<BoundOrderBy>,
+}
+
+/// An `INSERT` ready to be executed
+#[derive(Debug, Clone, PartialEq)]
+pub struct BoundInsert {
+    pub table: String,
+    /// Position in the table of each value of a row
+    pub columns: Vec<usize>,
+    pub rows: Vec<Vec<BoundExpr>>,
+}
+
+/// Resolves statements against a catalog
+pub struct Binder<'c> {
+    catalog: &'c Catalog,
+}
+
+/// A value of type `ty`, which an operator can be applied to in order to
+/// find the type of its result
+fn sample(ty: Option<DataType>) -> Value {
+    match ty {
+        None => Value::Null,
+        Some(DataType::Integer) => Value::Integer(1),
+        Some(DataType::Float) => Value::Float(1.0),
+        Some(DataType::Text) => Value::Text(String::new()),
+        Some(DataType::Blob) => Value::Blob(Vec::new()),
+    }
+}
+
+fn type_name(ty: Option<DataType>) -> String {
+    ty.map_or("NULL".into(), |t| t.to_string())
+}
+
+/// Find a column of `table`, which must be the table named by `qualifier`
+/// if one is given
+fn resolve(
+    table: Option<&TableSchema>,
+    qualifier: Option<&Token>,
+    column: &Token,
+    span: Span,
+) -> Result<(usize, DataType)> {
+    table
+        .filter(|t| qualifier.is_none_or(|q| q.name() == Some(&t.name)))
+        .and_then(|t| t.column(column.name()?))
+        .map(|(i, c)| (i, c.ty))
+        .ok_or_else(|| Error::UnknownColumn {
+            name: match qualifier {
+                Some(q) => format!("{}.{}", q, column),
+                None => column.to_string(),
+            },
+            span,
+        })
+}
+
+/// Resolve and type an expression. Column references are resolved against
+/// `table`, and are an error if there is none
+pub fn bind_expr(expr: &Expr, table: Option<&TableSchema>) -> Result<BoundExpr> {
+    let span = expr.span;
+    let (kind, ty) = match expr.kind {
+        ExprKind::Literal(ref tok) => {
+            let value = Value::from_literal(tok).expect("Literal is not a value");
+            let ty = value.data_type();
+            (BoundExprKind::Literal(value), ty)
+        }
+        ExprKind::Column(ref c) => {
+            let (i, ty) = resolve(table, None, c, span)?;
+            (BoundExprKind::Column(i), Some(ty))
+        }
+        ExprKind::QualifiedColumn(ref t, ref c) => {
+            let (i, ty) = resolve(table, Some(t), c, span)?;
+            (BoundExprKind::Column(i), Some(ty))
+        }
+        ExprKind::Nested(ref e) => return bind_expr(e, table).map(|e| BoundExpr { span, ..e }),
+        ExprKind::Placeholder(ref p) => (BoundExprKind::Placeholder(p.clone().into_owned()), None),
+        ExprKind::Unary(op, ref e) => {
+            let e = bind_expr(e, table)?;
+            let ty = sample(e.ty)
+                .unary(op)
+                .map_err(|_| Error::InvalidOperands {
+                    op: op.to_string(),
+                    operands: vec![type_name(e.ty)],
+                    span,
+                })?
+                .data_type();
+            (BoundExprKind::Unary(op, Box::new(e)), ty)
+        }
+        ExprKind::Binary(ref l, op, ref r) => {
+            let (l, r) = (bind_expr(l, table)?, bind_expr(r, table)?);
+            let ty = sample(l.ty)
+                .binary(op, &sample(r.ty))
+                .map_err(|_| Error::InvalidOperands {
+                    op: op.to_string(),
+                    operands: vec![type_name(l.ty), type_name(r.ty)],
+                    span,
+                })?
+                .data_type();
+            (BoundExprKind::Binary(Box::new(l), op, Box::new(r)), ty)
+        }
+    };
+    Ok(BoundExpr { kind, ty, span })
+}
+
+/// Resolve and type a condition, which must be numeric or `NULL`
+fn bind_condition(expr: &Expr, table: &TableSchema) -> Result<BoundExpr> {
+    let bound = bind_expr(expr, Some(table))?;
+    match sample(bound.ty).truth() {
+        Some(_) => Ok(bound),
+        None => Err(Error::TypeMismatch {
+            expected: "boolean".into(),
+            found: type_name(bound.ty),
+            span: expr.span,
+        }),
+    }
+}
+
+/// Every column of `table`, as result columns
+fn all_columns(table: &TableSchema, span: Span) -> Vec<BoundColumn> {
+    table
+        .columns
+        .iter()
+        .enumerate()
+        .map(|(i, c)| BoundColumn {
+            name: c.name.clone(),
+            expr: BoundExpr {
+                kind: BoundExprKind::Column(i),
+                ty: Some(c.ty),
+                span,
+            },
+        })
+        .collect()
+}
+
+impl<'c> Binder<'c> {
+    pub fn new(catalog: &'c Catalog) -> Binder<'c> {
+        Binder { catalog }
+    }
+
+    pub fn bind_select(&self, select: &Select) -> Result<BoundSelect> {
+        let table = self.catalog.resolve(&select.table.token, select.table.span)?;
+
+        let mut columns = Vec::new();
+        for column in &select.columns {
+            match *column {
+                Column::All(span) => columns.extend(all_columns(table, span)),
+                Column::AllFrom(ref t, span) => {
+                    if t.name() != Some(&table.name) {
+                        return Err(Error::UnknownTable {
+                            name: t.to_string(),
+                            span,
+                        });
+                    }
+                    columns.extend(all_columns(table, span));
+                }
+                Column::Expr(ref expr, _) => columns.push(BoundColumn {
+                    name: column.name().unwrap_or_default(),
+                    expr: bind_expr(expr, Some(table))?,
+                }),
+            }
+        }
+
+        let predicate = match select.predicate {
+            Some(ref p) => Some(bind_condition(p, table)?),
+            None => None,
+        };
+        let order_by = select
+            .order_by
+            .iter()
+            .map(|o| {
+                Ok(BoundOrderBy {
+                    expr: bind_expr(&o.expr, Some(table))?,
+                    descending: o.descending,
+                })
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(BoundSelect {
+            table: table.name.clone(),
+            table_span: select.table.span,
+            columns,
+            predicate,
+            order_by,
+        })
+    }
+
+    /// Resolve the target columns of an insert, and check that every value
+    /// can be stored in its column. Values may not refer to any columns
+    pub fn bind_insert(&self, insert: &Insert) -> Result<BoundInsert> {
+        let table = self.catalog.resolve(&insert.table.token, insert.table.span)?;
+
+        let mut columns = Vec::new();
+        for c in &insert.columns {
+            let (i, _) = table.resolve(&c.token, c.span)?;
+            if columns.contains(&i) {
+                return Err(Error::DuplicateColumn {
+                    name: c.to_string(),
+                    span: c.span,
+                });
+            }
+            columns.push(i);
+        }
+        if insert.columns.is_empty() {
+            columns = (0..table.columns.len()).collect();
+        }
+
+        let mut rows = Vec::new();
+        for row in &insert.rows {
+            if row.len() != columns.len() {
+                let span = match (row.first(), row.last()) {
+                    (Some(first), Some(last)) => first.span.extend_to(last.span.end),
+                    _ => insert.span,
+                };
+                return Err(Error::ValueCount {
+                    expected: columns.len(),
+                    found: row.len(),
+                    span,
+                });
+            }
+            let mut values = Vec::new();
+            for (expr, &i) in row.iter().zip(&columns) {
+                let value = bind_expr(expr, None)?;
+                let ty = table.columns[i].ty;
+                if sample(value.ty).coerce(ty).is_none() {
+                    return Err(Error::TypeMismatch {
+                        expected: ty.to_string(),
+                        found: type_name(value.ty),
+                        span: expr.span,
+                    });
+                }
+                values.push(value);
+            }
+            rows.push(values);
+        }
+
+        Ok(BoundInsert {
+            table: table.name.clone(),
+            columns,
+            rows,
+        })
+    }
+
+    /// Check a statement against the catalog without running it. Use
+    /// `check_script` for statements that depend on those before them
+    pub fn check(&self, statement: &Statement) -> Result<()> {
+        match *statement {
+            Statement::Select(ref s) => self.bind_select(s).map(|_| ()),
+            Statement::Insert(ref s) => self.bind_insert(s).map(|_| ()),
+            Statement::CreateTable(ref s) => self.catalog.clone().create_table(s).map(|_| ()),
+            Statement::DropTable(ref s) => self.catalog.clone().drop_table(s).map(|_| ()),
+        }
+    }
+}
+
+/// Check a script against `catalog` without running it. Tables created and
+/// dropped by each statement are seen by the statements after it, but
+/// `catalog` itself is left unchanged
+pub fn check_script(catalog: &Catalog, statements: &[Statement]) -> Result<()> {
+    let mut catalog = catalog.clone();
+    for statement in statements {
+        match *statement {
+            Statement::CreateTable(ref s) => {
+                catalog.create_table(s)?;
+            }
+            Statement::DropTable(ref s) => {
+                catalog.drop_table(s)?;
+            }
+            ref s => Binder::new(&catalog).check(s)?,
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use syntax::parse_script;
+
+    fn catalog() -> Catalog {
+        let mut catalog = Catalog::new();
+        let script = "create table t (i int, f float, s text, b blob)";
+        for statement in parse_script(script).unwrap() {
+            if let Statement::CreateTable(ref create) = statement {
+                catalog.create_table(create).unwrap();
+            }
+        }
+        catalog
+    }
+
+    fn check(s: &str) -> Result<()> {
+        check_script(&catalog(), &parse_script(s)?)
+    }
+
+    fn select(s: &str) -> BoundSelect {
+        let catalog = catalog();
+        match parse_script(s).unwrap().remove(0) {
+            Statement::Select(ref select) => Binder::new(&catalog).bind_select(select).unwrap(),
+            s => panic!("Expected select, found {:?}", s),
+        }
+    }
+
+    #[test]
+    fn types() {
+        let bound = select("select i + 1, i * f, -i, s || i, b || b, i < f, null, i + null from t");
+        let types: Vec<_> = bound.columns.iter().map(|c| c.expr.ty).collect();
+        assert_eq!(
+            types,
+            vec![
+                Some(DataType::Integer),
+                Some(DataType::Float),
+                Some(DataType::Integer),
+                Some(DataType::Text),
+                Some(DataType::Blob),
+                Some(DataType::Integer),
+                None,
+                None,
+            ]
+        );
+    }
+
+    #[test]
+    fn expand_all() {
+        let bound = select("select *, t.s, t.* from t where t.i > 0 order by f desc");
+        let names: Vec<_> = bound.columns.iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(names, vec!["i", "f", "s", "b", "s", "i", "f", "s", "b"]);
+        assert_eq!(bound.columns[4].expr.kind, BoundExprKind::Column(2));
+        assert_eq!(bound.columns[8].expr.ty, Some(DataType::Blob));
+        assert_eq!(bound.order_by[0].expr.kind, BoundExprKind::Column(1));
+        assert!(bound.order_by[0].descending);
+    }
+
+    #[test]
+    fn type_errors() {
+        let message = |s| check(s).unwrap_err().message();
+        assert_eq!(message("select s + i from t"), "cannot apply `+` to TEXT and INTEGER");
+        assert_eq!(message("select * from t where b = f"), "cannot apply `=` to BLOB and FLOAT");
+        assert_eq!(message("select -s from t"), "cannot apply `-` to TEXT");
+        assert_eq!(message("select i from t where s"), "expected boolean, found TEXT");
+        assert_eq!(message("select s || b from t"), "cannot apply `||` to TEXT and BLOB");
+        assert_eq!(
+            message("select i from t where not (s and i)"),
+            "cannot apply `AND` to TEXT and INTEGER"
+        );
+        assert!(check("select s = 'x', i = f, null = b, ? + 1 from t where i and f").is_ok());
+    }
+
+    #[test]
+    fn name_errors() {
+        let message = |s| check(s).unwrap_err().message();
+        assert_eq!(message("select * from u"), "no such table `u`");
+        assert_eq!(message("select u.* from t"), "no such table `u`");
+        assert_eq!(message("select x from t"), "no such column `x`");
+        assert_eq!(message("select u.i from t"), "no such column `u.i`");
+        assert_eq!(message("select i from t order by \"I\""), "no such column `\"I\"`");
+        assert_eq!(message("drop table u"), "no such table `u`");
+        assert_eq!(message("create table T (x int)"), "table `t` already exists");
+        assert!(check("drop table if exists u; create table if not exists t (x int)").is_ok());
+        assert!(check("select name from shard_master").is_ok());
+
+        // Unknown names are reported where they appear
+        let span = |s| check(s).unwrap_err().span();
+        assert_eq!(span("select a from u"), Span::new(14, 15, 1, 15));
+        assert_eq!(span("insert into t (i, nme) values (1, 'a')"), Span::new(18, 21, 1, 19));
+        assert_eq!(span("insert into t (i, I) values (1, 2)"), Span::new(18, 19, 1, 19));
+        assert_eq!(span("drop table t, u"), Span::new(14, 15, 1, 15));
+        assert_eq!(span("create table T (x int)"), Span::new(13, 14, 1, 14));
+    }
+
+    #[test]
+    fn insert() {
+        let catalog = catalog();
+        let insert = match parse_script("insert into t (s, f) values ('a', 1), (null, 2.5)")
+            .unwrap()
+            .remove(0)
+        {
+            Statement::Insert(ref insert) => Binder::new(&catalog).bind_insert(insert).unwrap(),
+            s => panic!("Expected insert, found {:?}", s),
+        };
+        assert_eq!(insert.columns, vec![2, 1]);
+        assert_eq!(insert.rows[1][0].ty, None);
+
+        let message = |s| check(s).unwrap_err().message();
+        assert_eq!(message("insert into t values (1, 2.0, 'x')"), "expected 4 values, found 3");
+        assert_eq!(message("insert into t (i) values (1.5)"), "expected INTEGER, found FLOAT");
+        assert_eq!(message("insert into t (s, S) values ('a', 'b')"), "duplicate column `s`");
+        assert_eq!(message("insert into t (x) values (1)"), "no such column `x`");
+        assert_eq!(message("insert into t (i) values (i)"), "no such column `i`");
+        assert!(check("insert into t values (1, 2, 'x', null)").is_ok());
+    }
+
+    #[test]
+    fn script() {
+        assert!(check("create table x (y int); select y from x; insert into x values (1)").is_ok());
+        assert!(check("drop table t; create table t (s text); select s || s from t").is_ok());
+        let message = |s| check(s).unwrap_err().message();
+        assert_eq!(message("drop table t; select i from t"), "no such table `t`");
+        assert_eq!(
+            message("create table x (y int); create table x (z int)"),
+            "table `x` already exists"
+        );
+        assert_eq!(message("create table x (y int); select z from x"), "no such column `z`");
+
+        // Each statement on its own is checked against the catalog as it is
+        let catalog = catalog();
+        let statements = parse_script("create table x (y int); select y from x").unwrap();
+        assert!(Binder::new(&catalog).check(&statements[0]).is_ok());
+        assert!(Binder::new(&catalog).check(&statements[1]).is_err());
+        assert!(catalog.table("x").is_none());
+    }
+}
@@ -0,0 +1,559 @@
+//! The system catalog, which records the schema of every table
+//!
+//! Each table is stored as the `CREATE TABLE` statement that defined it,
+//! written in canonical form. The catalog can be read as the table
+//! `shard_master`, much like SQLite's `sqlite_master`. Those rows are saved
+//! to a chain of pages in the database file, and the catalog is rebuilt by
+//! parsing the statements again when it is loaded
+use std::io::{self, Read, Seek, Write};
+use std::str;
+use error::{Error, Result};
+use storage::{chain, PageId, Pager};
+use syntax::ast::create::CreateTable;
+use syntax::ast::drop::DropTable;
+use syntax::ast::statement::Statement;
+use syntax::parse_script;
+use syntax::token::{Span, Token};
+use super::binder::bind_expr;
+use super::eval::eval;
+use super::value::{DataType, Value};
+use super::Table;
+
+/// Name of the table that lists the schema of every other table
+pub const MASTER_TABLE: &str = "shard_master";
+
+const MASTER_SQL: &str =
+    "CREATE TABLE shard_master (type TEXT NOT NULL, name TEXT NOT NULL, sql TEXT NOT NULL)";
+
+/// First page of the chain holding the rows of `shard_master`. It is the
+/// first page allocated in a new database
+pub const CATALOG_PAGE: PageId = 1;
+
+/// Schema of a single column
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColumnSchema {
+    pub name: String,
+    pub ty: DataType,
+    pub not_null: bool,
+    /// Value of the `DEFAULT` expression, evaluated when the table is created
+    pub default: Option<Value>,
+    pub serial: bool,
+}
+
+/// Schema of a table
+#[derive(Debug, Clone, PartialEq)]
+pub struct TableSchema {
+    pub name: String,
+    pub columns: Vec<ColumnSchema>,
+    /// The `CREATE TABLE` statement that defined the table
+    pub sql: String,
+}
+
+/// Schemas of all tables, in the order they were created
+#[derive(Debug, Clone, PartialEq)]
+pub struct Catalog {
+    master: TableSchema,
+    tables: Vec<TableSchema>,
+}
+
+impl TableSchema {
+    /// Check a table definition, and evaluate its column defaults
+    pub fn from_create(create: &CreateTable) -> Result<TableSchema> {
+        let mut columns: Vec<ColumnSchema> = Vec::new();
+        for def in &create.columns {
+            let name = def.name.token.name().unwrap_or_default();
+            if columns.iter().any(|c| c.name == name) {
+                return Err(Error::DuplicateColumn {
+                    name: def.name.to_string(),
+                    span: def.span,
+                });
+            }
+            let ty = DataType::from_token(&def.ty).expect("Column type is not a type");
+            if def.serial && ty != DataType::Integer {
+                return Err(Error::TypeMismatch {
+                    expected: "INTEGER for a SERIAL column".into(),
+                    found: ty.to_string(),
+                    span: def.span,
+                });
+            }
+            let default = match def.default {
+                Some(ref expr) => {
+                    // Defaults are constant, so there are no columns in scope
+                    let value = eval(&bind_expr(expr, None)?, &[])?;
+                    Some(value.coerce(ty).ok_or_else(|| Error::TypeMismatch {
+                        expected: ty.to_string(),
+                        found: value.type_name(),
+                        span: expr.span,
+                    })?)
+                }
+                None => None,
+            };
+            columns.push(ColumnSchema {
+                name: name.into(),
+                ty,
+                not_null: def.not_null,
+                default,
+                serial: def.serial,
+            });
+        }
+
+        let definition = CreateTable {
+            if_not_exists: false,
+            ..create.clone()
+        };
+        Ok(TableSchema {
+            name: create.name.token.name().unwrap_or_default().into(),
+            columns,
+            sql: definition.to_string(),
+        })
+    }
+
+    /// Find a column by name, with its position in the table
+    pub fn column(&self, name: &str) -> Option<(usize, &ColumnSchema)> {
+        self.columns.iter().enumerate().find(|&(_, c)| c.name == name)
+    }
+
+    /// Resolve a column reference, reporting an error at `span` if the
+    /// table has no such column
+    pub fn resolve(&self, column: &Token, span: Span) -> Result<(usize, &ColumnSchema)> {
+        column
+            .name()
+            .and_then(|name| self.column(name))
+            .ok_or_else(|| Error::UnknownColumn {
+                name: column.to_string(),
+                span,
+            })
+    }
+
+    /// Names of the table's columns, in order
+    pub fn column_names(&self) -> Vec<String> {
+        self.columns.iter().map(|c| c.name.clone()).collect()
+    }
+}
+
+impl Default for Catalog {
+    fn default() -> Catalog {
+        Catalog::new()
+    }
+}
+
+impl Catalog {
+    /// An empty catalog, holding only the schema of `shard_master` itself
+    pub fn new() -> Catalog {
+        let master = match parse_script(MASTER_SQL).map(|mut s| s.remove(0)) {
+            Ok(Statement::CreateTable(ref create)) => TableSchema::from_create(create).ok(),
+            _ => None,
+        };
+        Catalog {
+            master: master.expect("Invalid master table definition"),
+            tables: Vec::new(),
+        }
+    }
+
+    /// Rebuild a catalog from the rows of `shard_master`
+    pub fn from_master(master: &Table) -> Result<Catalog> {
+        let mut catalog = Catalog::new();
+        for (i, row) in master.rows.iter().enumerate() {
+            let corrupt = |message: String| Error::CorruptCatalog {
+                message: format!("row {}: {}", i + 1, message),
+            };
+            let sql = match row.get(2) {
+                Some(Value::Text(sql)) => sql,
+                _ => return Err(corrupt("no table definition".into())),
+            };
+            for statement in parse_script(sql).map_err(|e| corrupt(e.message()))? {
+                match statement {
+                    Statement::CreateTable(ref create) => {
+                        catalog.create_table(create).map_err(|e| corrupt(e.message()))?;
+                    }
+                    s => return Err(corrupt(format!("`{}` does not define a table", s))),
+                }
+            }
+        }
+        Ok(catalog)
+    }
+
+    /// Write the rows of `shard_master` to a database file, so that the
+    /// catalog can be loaded when the file is opened again. Like any other
+    /// change, they are only in the file once the pager is flushed
+    pub fn save<F: Read + Write + Seek>(&self, pager: &mut Pager<F>) -> Result<()> {
+        if pager.page_count() <= CATALOG_PAGE {
+            pager.allocate().map_err(storage_error)?;
+        }
+        chain::write(pager, CATALOG_PAGE, &encode(&self.master())).map_err(storage_error)
+    }
+
+    /// Read the catalog saved in a database file. A database that has never
+    /// had a catalog saved to it has no tables
+    pub fn load<F: Read + Write + Seek>(pager: &mut Pager<F>) -> Result<Catalog> {
+        if pager.page_count() <= CATALOG_PAGE {
+            return Ok(Catalog::new());
+        }
+        let bytes = chain::read(pager, CATALOG_PAGE).map_err(storage_error)?;
+        Catalog::from_master(&decode(&bytes)?)
+    }
+
+    /// Contents of `shard_master`: the type, name and definition of each
+    /// table
+    pub fn master(&self) -> Table {
+        Table {
+            columns: self.master.column_names(),
+            rows: self
+                .tables
+                .iter()
+                .map(|t| vec!["table".into(), t.name.clone().into(), t.sql.clone().into()])
+                .collect(),
+        }
+    }
+
+    pub fn table(&self, name: &str) -> Option<&TableSchema> {
+        if name == MASTER_TABLE {
+            return Some(&self.master);
+        }
+        self.tables.iter().find(|t| t.name == name)
+    }
+
+    /// All user tables, in the order they were created
+    pub fn tables(&self) -> &[TableSchema] {
+        &self.tables
+    }
+
+    /// Resolve a table name, reporting an error at `span` if there is no
+    /// such table
+    pub fn resolve(&self, table: &Token, span: Span) -> Result<&TableSchema> {
+        table
+            .name()
+            .and_then(|name| self.table(name))
+            .ok_or_else(|| Error::UnknownTable {
+                name: table.to_string(),
+                span,
+            })
+    }
+
+    /// Add a table to the catalog. Returns false if the table already
+    /// existed, and the statement was `CREATE TABLE IF NOT EXISTS`
+    pub fn create_table(&mut self, create: &CreateTable) -> Result<bool> {
+        let name = create.name.token.name().unwrap_or_default();
+        if self.table(name).is_some() {
+            if create.if_not_exists && name != MASTER_TABLE {
+                return Ok(false);
+            }
+            return Err(Error::TableExists {
+                name: create.name.to_string(),
+                span: create.name.span,
+            });
+        }
+        let schema = TableSchema::from_create(create)?;
+        self.tables.push(schema);
+        Ok(true)
+    }
+
+    /// Remove tables from the catalog, returning the schemas of those that
+    /// were dropped. Nothing is dropped if any table does not exist, unless
+    /// the statement was `DROP TABLE IF EXISTS`
+    pub fn drop_table(&mut self, drop: &DropTable) -> Result<Vec<TableSchema>> {
+        let mut dropped = Vec::new();
+        for table in &drop.names {
+            let name = table.token.name().unwrap_or_default();
+            if name == MASTER_TABLE {
+                return Err(Error::SystemTable {
+                    name: table.to_string(),
+                    span: table.span,
+                });
+            }
+            match self.tables.iter().position(|t| t.name == name) {
+                Some(i) => dropped.push(i),
+                None if drop.if_exists => (),
+                None => {
+                    return Err(Error::UnknownTable {
+                        name: table.to_string(),
+                        span: table.span,
+                    })
+                }
+            }
+        }
+        dropped.sort_unstable();
+        dropped.dedup();
+        // Remove from the back, so the remaining positions stay valid
+        let mut schemas: Vec<TableSchema> =
+            dropped.into_iter().rev().map(|i| self.tables.remove(i)).collect();
+        schemas.reverse();
+        Ok(schemas)
+    }
+}
+
+fn storage_error(error: io::Error) -> Error {
+    Error::Storage {
+        message: error.to_string(),
+    }
+}
+
+fn put_u32(bytes: &mut Vec<u8>, n: usize) {
+    bytes.extend_from_slice(&(n as u32).to_be_bytes());
+}
+
+/// Lay out the rows of `shard_master` for storage: the number of rows, then
+/// for each row the number of values, each followed by its length and text.
+/// Numbers are stored in 4 bytes, big-endian
+fn encode(master: &Table) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    put_u32(&mut bytes, master.rows.len());
+    for row in &master.rows {
+        put_u32(&mut bytes, row.len());
+        for value in row {
+            let text = match *value {
+                Value::Text(ref text) => text,
+                _ => unreachable!("shard_master holds only text"),
+            };
+            put_u32(&mut bytes, text.len());
+            bytes.extend_from_slice(text.as_bytes());
+        }
+    }
+    bytes
+}
+
+/// Split `n` bytes off the front of `bytes`
+fn take<'a>(bytes: &mut &'a [u8], n: usize) -> Result<&'a [u8]> {
+    if bytes.len() < n {
+        return Err(Error::CorruptCatalog {
+            message: "stored rows are cut short".into(),
+        });
+    }
+    let (head, rest) = bytes.split_at(n);
+    *bytes = rest;
+    Ok(head)
+}
+
+fn take_u32(bytes: &mut &[u8]) -> Result<usize> {
+    take(bytes, 4).map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]) as usize)
+}
+
+/// Read back the rows written by `encode`
+fn decode(mut bytes: &[u8]) -> Result<Table> {
+    let mut master = Table {
+        columns: Catalog::new().master.column_names(),
+        rows: Vec::new(),
+    };
+    for _ in 0..take_u32(&mut bytes)? {
+        let mut row = Vec::new();
+        for _ in 0..take_u32(&mut bytes)? {
+            let len = take_u32(&mut bytes)?;
+            let text = str::from_utf8(take(&mut bytes, len)?).map_err(|_| {
+                Error::CorruptCatalog {
+                    message: "stored text is not valid UTF-8".into(),
+                }
+            })?;
+            row.push(Value::Text(text.into()));
+        }
+        master.rows.push(row);
+    }
+    if !bytes.is_empty() {
+        return Err(Error::CorruptCatalog {
+            message: "unexpected bytes after the stored rows".into(),
+        });
+    }
+    Ok(master)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, fs, process};
+
+    fn run(catalog: &mut Catalog, s: &str) -> Result<()> {
+        for statement in parse_script(s)? {
+            match statement {
+                Statement::CreateTable(ref create) => {
+                    catalog.create_table(create)?;
+                }
+                Statement::DropTable(ref drop) => {
+                    catalog.drop_table(drop)?;
+                }
+                s => panic!("Expected CREATE or DROP, found {}", s),
+            }
+        }
+        Ok(())
+    }
+
+    fn names(catalog: &Catalog) -> Vec<&str> {
+        catalog.tables().iter().map(|t| t.name.as_str()).collect()
+    }
+
+    #[test]
+    fn create_table() {
+        let mut catalog = Catalog::new();
+        run(
+            &mut catalog,
+            "create table Users (id int not null serial, name text default 'x', \
+             score float default 1 + 1)",
+        )
+        .unwrap();
+        let users = catalog.table("users").unwrap();
+        assert_eq!(
+            users.columns[0],
+            ColumnSchema {
+                name: "id".into(),
+                ty: DataType::Integer,
+                not_null: true,
+                default: None,
+                serial: true,
+            }
+        );
+        assert_eq!(users.columns[1].default, Some("x".into()));
+        assert_eq!(users.columns[2].default, Some(Value::Float(2.0)));
+        assert_eq!(
+            users.sql,
+            "CREATE TABLE users (id INTEGER NOT NULL SERIAL, name TEXT DEFAULT 'x', \
+             score FLOAT DEFAULT 1 + 1)"
+        );
+        assert_eq!(users.column("score").map(|(i, _)| i), Some(2));
+        assert_eq!(
+            users.resolve(&Token::QuotedIdentifier("Name".into()), Span::default()),
+            Err(Error::UnknownColumn {
+                name: "\"Name\"".into(),
+                span: Span::default(),
+            })
+        );
+    }
+
+    #[test]
+    fn create_errors() {
+        let mut catalog = Catalog::new();
+        run(&mut catalog, "create table t (a int)").unwrap();
+        let error = |s| run(&mut catalog.clone(), s).unwrap_err().message();
+        assert_eq!(error("create table T (b int)"), "table `t` already exists");
+        assert_eq!(
+            error("create table shard_master (b int)"),
+            "table `shard_master` already exists"
+        );
+        assert_eq!(error("create table u (a int, A text)"), "duplicate column `a`");
+        assert_eq!(
+            error("create table u (a text serial)"),
+            "expected INTEGER for a SERIAL column, found TEXT"
+        );
+        assert_eq!(error("create table u (a int default 'x')"), "expected INTEGER, found TEXT");
+        assert_eq!(error("create table u (a int default b)"), "no such column `b`");
+
+        run(&mut catalog, "create table if not exists t (b text)").unwrap();
+        assert_eq!(catalog.table("t").unwrap().columns[0].name, "a");
+    }
+
+    #[test]
+    fn drop_table() {
+        let mut catalog = Catalog::new();
+        run(&mut catalog, "create table a (x int); create table b (x int); create table c (x int)")
+            .unwrap();
+        assert_eq!(
+            run(&mut catalog, "drop table a, missing").unwrap_err().message(),
+            "no such table `missing`"
+        );
+        assert_eq!(names(&catalog), vec!["a", "b", "c"]);
+        run(&mut catalog, "drop table if exists c, missing, a").unwrap();
+        assert_eq!(names(&catalog), vec!["b"]);
+        for s in &["drop table shard_master", "drop table if exists b, Shard_Master"] {
+            assert_eq!(
+                run(&mut catalog, s).unwrap_err().message(),
+                "cannot drop system table `shard_master`"
+            );
+        }
+        assert_eq!(names(&catalog), vec!["b"]);
+    }
+
+    #[test]
+    fn master() {
+        let mut catalog = Catalog::new();
+        run(&mut catalog, "create table t (a int default -1); create table \"U\" (b blob not null)")
+            .unwrap();
+        let master = catalog.master();
+        assert_eq!(master.columns, vec!["type", "name", "sql"]);
+        assert_eq!(
+            master.rows[1],
+            vec![
+                "table".into(),
+                "U".into(),
+                "CREATE TABLE \"U\" (b BLOB NOT NULL)".into(),
+            ]
+        );
+        assert_eq!(Catalog::from_master(&master), Ok(catalog));
+        assert!(Catalog::new().resolve(&Token::from_str("Shard_Master"), Span::default()).is_ok());
+    }
+
+    #[test]
+    fn persistence() {
+        let path = env::temp_dir().join(format!("shard-catalog-{}.db", process::id()));
+        let _ = fs::remove_file(&path);
+        let mut catalog = Catalog::new();
+        {
+            let mut pager = Pager::create_file(&path, 2).unwrap();
+            assert_eq!(Catalog::load(&mut pager), Ok(Catalog::new()));
+            run(&mut catalog, "create table t (a int default 1); create table \"é\" (b text)")
+                .unwrap();
+            catalog.save(&mut pager).unwrap();
+            pager.flush().unwrap();
+        }
+        let mut pager = Pager::open_file(&path, 2).unwrap();
+        assert_eq!(Catalog::load(&mut pager), Ok(catalog.clone()));
+
+        // Enough tables to fill several pages, then fewer again
+        for i in 0..200 {
+            run(&mut catalog, &format!("create table table_{} (column_{} text)", i, i)).unwrap();
+        }
+        catalog.save(&mut pager).unwrap();
+        let mut pager = Pager::open(pager.into_inner().unwrap(), 1).unwrap();
+        assert!(pager.page_count() > 3);
+        assert_eq!(Catalog::load(&mut pager), Ok(catalog));
+        let catalog = Catalog::new();
+        catalog.save(&mut pager).unwrap();
+        assert_eq!(Catalog::load(&mut pager), Ok(catalog));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn corrupt_storage() {
+        let mut catalog = Catalog::new();
+        run(&mut catalog, "create table t (a int)").unwrap();
+        let bytes = encode(&catalog.master());
+        assert_eq!(decode(&bytes), Ok(catalog.master()));
+
+        let message = |bytes: &[u8]| decode(bytes).unwrap_err().message();
+        assert_eq!(
+            message(&bytes[..bytes.len() - 1]),
+            "corrupt catalog: stored rows are cut short"
+        );
+        assert_eq!(
+            message(&[&bytes[..], &[0]].concat()),
+            "corrupt catalog: unexpected bytes after the stored rows"
+        );
+        assert_eq!(
+            message(&[0, 0, 0, 1, 0, 0, 0, 1, 0, 0, 0, 1, 0xff]),
+            "corrupt catalog: stored text is not valid UTF-8"
+        );
+    }
+
+    #[test]
+    fn corrupt_master() {
+        let error = |sql: Option<Value>| {
+            let mut row: Vec<Value> = vec!["table".into(), "t".into()];
+            row.extend(sql);
+            let master = Table {
+                columns: Vec::new(),
+                rows: vec![row],
+            };
+            Catalog::from_master(&master).unwrap_err().message()
+        };
+        assert_eq!(error(None), "corrupt catalog: row 1: no table definition");
+        assert_eq!(error(Some(Value::Integer(1))), "corrupt catalog: row 1: no table definition");
+        assert_eq!(
+            error(Some("drop table t".into())),
+            "corrupt catalog: row 1: `DROP TABLE t` does not define a table"
+        );
+        assert_eq!(
+            error(Some("create table t (a int, a int)".into())),
+            "corrupt catalog: row 1: duplicate column `a`"
+        );
+        assert_eq!(
+            error(Some("create table".into())),
+            "corrupt catalog: row 1: expected identifier, found end of input"
+        );
+    }
+}
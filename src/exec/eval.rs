@@ -0,0 +1,164 @@
+//! Evaluation of bound expressions against a single row
+use error::{Error, Result};
+use syntax::token::Span;
+use super::binder::{BoundExpr, BoundExprKind};
+use super::value::{OpError, Value};
+
+/// Evaluate `expr` against a row of the table it was bound to
+pub fn eval(expr: &BoundExpr, row: &[Value]) -> Result<Value> {
+    match expr.kind {
+        BoundExprKind::Literal(ref value) => Ok(value.clone()),
+        // Rows may be filled in directly, so one can be too short
+        BoundExprKind::Column(i) => row.get(i).cloned().ok_or_else(|| Error::ShortRow {
+            column: i + 1,
+            span: expr.span,
+        }),
+        BoundExprKind::Placeholder(ref p) => Err(Error::UnboundPlaceholder {
+            placeholder: p.clone(),
+            span: expr.span,
+        }),
+        // The binder has checked the operand types, but a column may still
+        // hold a value of another type if it was filled in directly
+        BoundExprKind::Unary(op, ref e) => {
+            let v = eval(e, row)?;
+            v.unary(op).map_err(|e| op_error(e, op.to_string(), &[&v], expr.span))
+        }
+        BoundExprKind::Binary(ref l, op, ref r) => {
+            let (l, r) = (eval(l, row)?, eval(r, row)?);
+            l.binary(op, &r).map_err(|e| op_error(e, op.to_string(), &[&l, &r], expr.span))
+        }
+    }
+}
+
+/// Evaluate `expr` as a condition, which holds only if it is true
+pub fn condition(expr: &BoundExpr, row: &[Value]) -> Result<bool> {
+    let v = eval(expr, row)?;
+    match v.truth() {
+        Some(t) => Ok(t == Some(true)),
+        None => Err(Error::TypeMismatch {
+            expected: "boolean".into(),
+            found: v.type_name(),
+            span: expr.span,
+        }),
+    }
+}
+
+fn op_error(error: OpError, op: String, operands: &[&Value], span: Span) -> Error {
+    match error {
+        OpError::Invalid => Error::InvalidOperands {
+            op,
+            operands: operands.iter().map(|v| v.type_name()).collect(),
+            span,
+        },
+        OpError::Overflow => Error::IntegerOverflow { op, span },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use syntax::ast::Syntax;
+    use syntax::ast::expr::{Expr, UnaryOp};
+    use syntax::ast::statement::Statement;
+    use syntax::lexer::Lexer;
+    use syntax::parse_script;
+    use super::super::binder::bind_expr;
+    use super::super::catalog::TableSchema;
+    use super::super::value::DataType;
+
+    fn eval_str(s: &str) -> Result<Value> {
+        let table = match parse_script("create table t (a int, b int)").unwrap().remove(0) {
+            Statement::CreateTable(ref create) => TableSchema::from_create(create).unwrap(),
+            _ => unreachable!(),
+        };
+        let expr = Expr::parse(&mut Lexer::lex(s).unwrap()).unwrap();
+        eval(&bind_expr(&expr, Some(&table))?, &[Value::Integer(2), Value::Null])
+    }
+
+    #[test]
+    fn arithmetic() {
+        assert_eq!(eval_str("1 + a * 3"), Ok(Value::Integer(7)));
+        assert_eq!(eval_str("t.a / 4.0"), Ok(Value::Float(0.5)));
+        assert_eq!(eval_str("7 / 2"), Ok(Value::Integer(3)));
+        assert_eq!(eval_str("1 / 0"), Ok(Value::Null));
+        assert_eq!(eval_str("-a + b"), Ok(Value::Null));
+        assert_eq!(eval_str("'a' || 'b'"), Ok(Value::Text("ab".into())));
+    }
+
+    #[test]
+    fn three_valued_logic() {
+        assert_eq!(eval_str("a = 2"), Ok(Value::Integer(1)));
+        assert_eq!(eval_str("a < 1.5"), Ok(Value::Integer(0)));
+        assert_eq!(eval_str("b = b"), Ok(Value::Null));
+        assert_eq!(eval_str("b = 1 or a = 2"), Ok(Value::Integer(1)));
+        assert_eq!(eval_str("b = 1 and a = 2"), Ok(Value::Null));
+        assert_eq!(eval_str("b = 1 and a = 3"), Ok(Value::Integer(0)));
+        assert_eq!(eval_str("not b"), Ok(Value::Null));
+        assert_eq!(eval_str("'abc' < 'abd'"), Ok(Value::Integer(1)));
+    }
+
+    #[test]
+    fn untyped_values() {
+        // Values of the wrong type are still caught during evaluation
+        let expr = BoundExpr {
+            kind: BoundExprKind::Unary(
+                UnaryOp::Negate,
+                Box::new(BoundExpr {
+                    kind: BoundExprKind::Column(0),
+                    ty: Some(DataType::Integer),
+                    span: Span::new(1, 2, 1, 2),
+                }),
+            ),
+            ty: Some(DataType::Integer),
+            span: Span::new(0, 2, 1, 1),
+        };
+        assert_eq!(eval(&expr, &[5.into()]), Ok(Value::Integer(-5)));
+        assert_eq!(
+            eval(&expr, &["x".into()]).unwrap_err().message(),
+            "cannot apply `-` to TEXT"
+        );
+        assert_eq!(condition(&expr, &[Value::Null]), Ok(false));
+        assert_eq!(
+            eval(&expr, &[]),
+            Err(Error::ShortRow {
+                column: 1,
+                span: Span::new(1, 2, 1, 2),
+            })
+        );
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(
+            eval_str("a + 'x'"),
+            Err(Error::InvalidOperands {
+                op: "+".into(),
+                operands: vec!["INTEGER".into(), "TEXT".into()],
+                span: Span::new(0, 7, 1, 1),
+            })
+        );
+        assert_eq!(
+            eval_str("u.a").unwrap_err().message(),
+            "no such column `u.a`"
+        );
+        assert_eq!(
+            eval_str("\"A\"").unwrap_err().message(),
+            "no such column `\"A\"`"
+        );
+        assert_eq!(
+            eval_str("9223372036854775807 + a"),
+            Err(Error::IntegerOverflow {
+                op: "+".into(),
+                span: Span::new(0, 23, 1, 1),
+            })
+        );
+        assert_eq!(
+            eval_str("-(-9223372036854775807 - a)").unwrap_err().message(),
+            "integer overflow in `-`"
+        );
+        assert_eq!(
+            eval_str("a = ?").unwrap_err().message(),
+            "no value bound to placeholder `?`"
+        );
+    }
+}
@@ -0,0 +1,304 @@
+//! Execution of queries against in-memory tables
+//!
+//! A `SELECT` is first bound to the catalog, which checks its names and
+//! types. It is then run by scanning every row of its table, keeping those
+//! that satisfy the `WHERE` predicate, sorting them by the `ORDER BY`
+//! terms and finally evaluating the projection for each row
+//!
+//! Tables are created and dropped through the `Catalog`, and the catalog
+//! itself can be queried as the table `shard_master`. Only the catalog is
+//! saved to a database file; the rows of tables are held in memory
+
+pub mod binder;
+pub mod catalog;
+pub mod value;
+mod eval;
+
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use error::{Error, Result};
+use syntax::ast::create::CreateTable;
+use syntax::ast::drop::DropTable;
+use syntax::ast::select::Select;
+use self::binder::{Binder, BoundSelect};
+use self::catalog::{Catalog, MASTER_TABLE};
+use self::eval::{condition, eval};
+use self::value::Value;
+
+/// A single row, with one value for each column
+pub type Row = Vec<Value>;
+
+/// A table of rows held in memory
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Table {
+    pub columns: Vec<String>,
+    pub rows: Vec<Row>,
+}
+
+/// Rows returned by a query, along with the names of their columns
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rows {
+    pub columns: Vec<String>,
+    pub rows: Vec<Row>,
+}
+
+/// A set of named tables that queries can be run against
+#[derive(Debug, Default)]
+pub struct Database {
+    catalog: Catalog,
+    tables: HashMap<String, Table>,
+}
+
+impl Database {
+    pub fn new() -> Database {
+        Database::default()
+    }
+
+    pub fn catalog(&self) -> &Catalog {
+        &self.catalog
+    }
+
+    /// Create an empty table, recording its schema in the catalog
+    pub fn create_table(&mut self, create: &CreateTable) -> Result<()> {
+        if self.catalog.create_table(create)? {
+            let schema = self.catalog.tables().last().expect("Table was not created");
+            let table = Table {
+                columns: schema.column_names(),
+                rows: Vec::new(),
+            };
+            self.tables.insert(schema.name.clone(), table);
+        }
+        Ok(())
+    }
+
+    /// Drop tables along with all of their rows
+    pub fn drop_table(&mut self, drop: &DropTable) -> Result<()> {
+        for schema in self.catalog.drop_table(drop)? {
+            self.tables.remove(&schema.name);
+        }
+        Ok(())
+    }
+
+    pub fn table(&self, name: &str) -> Option<&Table> {
+        self.tables.get(name)
+    }
+
+    pub fn table_mut(&mut self, name: &str) -> Option<&mut Table> {
+        self.tables.get_mut(name)
+    }
+
+    /// Bind a query to the catalog and run it, returning the selected rows
+    pub fn select(&self, select: &Select) -> Result<Rows> {
+        let bound = Binder::new(&self.catalog).bind_select(select)?;
+        self.execute_select(&bound)
+    }
+
+    /// Run a query that has already been bound to the catalog
+    pub fn execute_select(&self, select: &BoundSelect) -> Result<Rows> {
+        let master;
+        let table = if select.table == MASTER_TABLE {
+            master = self.catalog.master();
+            &master
+        } else {
+            self.table(&select.table).ok_or_else(|| Error::UnknownTable {
+                name: select.table.clone(),
+                span: select.table_span,
+            })?
+        };
+
+        let mut selected = Vec::new();
+        for row in &table.rows {
+            if let Some(ref predicate) = select.predicate {
+                if !condition(predicate, row)? {
+                    continue;
+                }
+            }
+            let keys = select
+                .order_by
+                .iter()
+                .map(|o| eval(&o.expr, row))
+                .collect::<Result<Vec<_>>>()?;
+            selected.push((keys, row));
+        }
+
+        // The sort is stable, so rows that compare equal stay in table order
+        selected.sort_by(|(a, _), (b, _)| {
+            a.iter()
+                .zip(b)
+                .zip(&select.order_by)
+                .map(|((a, b), o)| {
+                    let ordering = a.sort_cmp(b);
+                    if o.descending {
+                        ordering.reverse()
+                    } else {
+                        ordering
+                    }
+                })
+                .find(|&o| o != Ordering::Equal)
+                .unwrap_or(Ordering::Equal)
+        });
+
+        let rows = selected
+            .into_iter()
+            .map(|(_, row)| select.columns.iter().map(|c| eval(&c.expr, row)).collect())
+            .collect::<Result<Vec<Row>>>()?;
+
+        Ok(Rows {
+            columns: select.columns.iter().map(|c| c.name.clone()).collect(),
+            rows,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use syntax::parse_script;
+    use syntax::ast::statement::Statement;
+
+    /// Run a script, returning the rows of its last statement
+    fn run(db: &mut Database, s: &str) -> Result<Option<Rows>> {
+        let mut rows = None;
+        for statement in parse_script(s)? {
+            rows = match statement {
+                Statement::Select(ref select) => Some(db.select(select)?),
+                Statement::CreateTable(ref create) => db.create_table(create).map(|_| None)?,
+                Statement::DropTable(ref drop) => db.drop_table(drop).map(|_| None)?,
+                s => panic!("Expected select, create or drop, found {:?}", s),
+            };
+        }
+        Ok(rows)
+    }
+
+    fn database() -> Database {
+        let mut db = Database::new();
+        run(&mut db, "create table users (id int not null, name text, score float)").unwrap();
+        db.table_mut("users").unwrap().rows = vec![
+            vec![1.into(), "alice".into(), 2.5.into()],
+            vec![2.into(), "bob".into(), Value::Null],
+            vec![3.into(), "carol".into(), 4.0.into()],
+            vec![4.into(), "dave".into(), 2.5.into()],
+        ];
+        db
+    }
+
+    fn query(db: &Database, s: &str) -> Result<Rows> {
+        match parse_script(s).unwrap().remove(0) {
+            Statement::Select(ref select) => db.select(select),
+            s => panic!("Expected select, found {:?}", s),
+        }
+    }
+
+    #[test]
+    fn select_all() {
+        let db = database();
+        let rows = query(&db, "select * from users").unwrap();
+        assert_eq!(rows.columns, vec!["id", "name", "score"]);
+        assert_eq!(rows.rows, db.table("users").unwrap().rows);
+    }
+
+    #[test]
+    fn select_where() {
+        let db = database();
+        let rows = query(
+            &db,
+            "select users.name, id * 10 as tens, score + 1 from users \
+             where score >= 2.5 and id <> 3",
+        )
+        .unwrap();
+        assert_eq!(rows.columns, vec!["name", "tens", "score + 1"]);
+        assert_eq!(
+            rows.rows,
+            vec![
+                vec!["alice".into(), 10.into(), 3.5.into()],
+                vec!["dave".into(), 40.into(), 3.5.into()],
+            ]
+        );
+    }
+
+    #[test]
+    fn select_order_by() {
+        let db = database();
+        let rows = query(&db, "select id, users.* from users order by score desc, name").unwrap();
+        assert_eq!(rows.columns, vec!["id", "id", "name", "score"]);
+        let ids: Vec<_> = rows.rows.iter().map(|r| r[0].clone()).collect();
+        // NULL sorts first, so it comes last when descending
+        assert_eq!(ids, vec![3.into(), 1.into(), 4.into(), 2.into()]);
+    }
+
+    #[test]
+    fn select_errors() {
+        let db = database();
+        assert_eq!(
+            query(&db, "select * from missing").unwrap_err().message(),
+            "no such table `missing`"
+        );
+        assert_eq!(
+            query(&db, "select t.* from users").unwrap_err().message(),
+            "no such table `t`"
+        );
+        assert_eq!(
+            query(&db, "select name from users where id = 9 or nope").unwrap_err().message(),
+            "no such column `nope`"
+        );
+        assert_eq!(
+            query(&db, "select id from users where name").unwrap_err().message(),
+            "expected boolean, found TEXT"
+        );
+
+        let mut db = database();
+        db.table_mut("users").unwrap().rows[0][0] = i64::MAX.into();
+        assert_eq!(
+            query(&db, "select id + 1 from users").unwrap_err().message(),
+            "integer overflow in `+`"
+        );
+        db.table_mut("users").unwrap().rows.push(vec![5.into()]);
+        assert_eq!(
+            query(&db, "select name from users").unwrap_err().message(),
+            "row has no value for column 2"
+        );
+
+        // A query bound before its table was dropped reports the table name
+        let s = "select id from users";
+        let bound = match parse_script(s).unwrap().remove(0) {
+            Statement::Select(ref select) => Binder::new(db.catalog()).bind_select(select).unwrap(),
+            s => panic!("Expected select, found {:?}", s),
+        };
+        run(&mut db, "drop table users").unwrap();
+        let error = db.execute_select(&bound).unwrap_err();
+        assert_eq!(error.message(), "no such table `users`");
+        assert_eq!(&s[error.span().start..error.span().end], "users");
+    }
+
+    #[test]
+    fn create_and_drop() {
+        let mut db = database();
+        let rows = run(
+            &mut db,
+            "create table if not exists users (x int); create table t (a int); \
+             select name, sql from shard_master where type = 'table' order by name desc",
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(rows.columns, vec!["name", "sql"]);
+        assert_eq!(
+            rows.rows,
+            vec![
+                vec![
+                    "users".into(),
+                    "CREATE TABLE users (id INTEGER NOT NULL, name TEXT, score FLOAT)".into(),
+                ],
+                vec!["t".into(), "CREATE TABLE t (a INTEGER)".into()],
+            ]
+        );
+        assert_eq!(query(&db, "select * from t").unwrap().rows, Vec::<Row>::new());
+
+        run(&mut db, "drop table users").unwrap();
+        assert!(db.table("users").is_none());
+        assert_eq!(
+            query(&db, "select * from users").unwrap_err().message(),
+            "no such table `users`"
+        );
+        assert_eq!(query(&db, "select name from shard_master").unwrap().rows.len(), 1);
+    }
+}
@@ -0,0 +1,493 @@
+//! Values stored in tables and produced by evaluating expressions
+//!
+//! Operators follow SQL's three valued logic: `NULL` stands for an unknown
+//! value, so any comparison or arithmetic involving it is also `NULL`, and
+//! conditions built from `AND`, `OR` and `NOT` are true, false or unknown.
+//! Comparisons yield `1`, `0` or `NULL`, and numbers are true when they
+//! are non-zero.
+//!
+//! Values are only converted between types by these rules:
+//!
+//! * `INTEGER` is widened to `FLOAT` when mixed with a `FLOAT`, or when it
+//!   is stored in a `FLOAT` column
+//! * `||` writes numbers as `TEXT`, but never mixes `TEXT` and `BLOB`
+//! * Anything else, such as `TEXT + INTEGER`, is an error
+//!
+//! Integer arithmetic that overflows is an error too, rather than being
+//! widened, so an expression always produces values of the type the binder
+//! gave it
+use std::cmp::Ordering;
+use std::fmt;
+use syntax::ast::expr::{BinaryOp, UnaryOp};
+use syntax::token::{write_float, Token};
+
+/// Declared type of a column
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DataType {
+    Integer,
+    Float,
+    Text,
+    Blob,
+}
+
+/// Why an operator could not be applied to its operands
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OpError {
+    /// The operator does not apply to values of these types
+    Invalid,
+    /// The result of integer arithmetic does not fit in 64 bits
+    Overflow,
+}
+
+/// A single SQL value
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Null,
+    Integer(i64),
+    Float(f64),
+    Text(String),
+    Blob(Vec<u8>),
+}
+
+impl DataType {
+    /// Match a type keyword into a data type
+    pub fn from_token(tok: &Token) -> Option<DataType> {
+        Some(match *tok {
+            Token::INTEGER => DataType::Integer,
+            Token::FLOAT => DataType::Float,
+            Token::TEXT => DataType::Text,
+            Token::BLOB => DataType::Blob,
+            _ => return None,
+        })
+    }
+
+    /// Is this `INTEGER` or `FLOAT`
+    pub fn is_numeric(self) -> bool {
+        self == DataType::Integer || self == DataType::Float
+    }
+}
+
+impl fmt::Display for DataType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = match *self {
+            DataType::Integer => "INTEGER",
+            DataType::Float => "FLOAT",
+            DataType::Text => "TEXT",
+            DataType::Blob => "BLOB",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+impl Value {
+    /// Value of a string, number or `NULL` literal
+    pub fn from_literal(tok: &Token) -> Option<Value> {
+        Some(match *tok {
+            Token::NULL => Value::Null,
+            Token::IntegerLiteral(i) => Value::Integer(i),
+            Token::FloatLiteral(x) => Value::Float(x),
+            Token::StringLiteral(ref s) => Value::Text(s.to_string()),
+            _ => return None,
+        })
+    }
+
+    /// Type of the value, or `None` for `NULL`
+    pub fn data_type(&self) -> Option<DataType> {
+        match *self {
+            Value::Null => None,
+            Value::Integer(_) => Some(DataType::Integer),
+            Value::Float(_) => Some(DataType::Float),
+            Value::Text(_) => Some(DataType::Text),
+            Value::Blob(_) => Some(DataType::Blob),
+        }
+    }
+
+    /// Name of the value's type, as it is written in SQL
+    pub fn type_name(&self) -> String {
+        self.data_type().map_or("NULL".into(), |t| t.to_string())
+    }
+
+    pub fn is_null(&self) -> bool {
+        *self == Value::Null
+    }
+
+    /// Truth value of the value as a condition, which is `Some(None)` if
+    /// it is unknown. `None` if the value cannot be a condition
+    pub fn truth(&self) -> Option<Option<bool>> {
+        match *self {
+            Value::Null => Some(None),
+            Value::Integer(i) => Some(Some(i != 0)),
+            Value::Float(x) => Some(Some(x != 0.0)),
+            Value::Text(_) | Value::Blob(_) => None,
+        }
+    }
+
+    /// Value of a condition: `1`, `0` or `NULL` if it is unknown
+    pub fn boolean(b: Option<bool>) -> Value {
+        b.map_or(Value::Null, |b| Value::Integer(b as i64))
+    }
+
+    fn number(&self) -> Option<f64> {
+        match *self {
+            Value::Integer(i) => Some(i as f64),
+            Value::Float(x) => Some(x),
+            _ => None,
+        }
+    }
+
+    /// Compare two values. Numbers compare with numbers, while text and
+    /// blobs only compare with their own type. The ordering is `None` if
+    /// either value is `NULL`, and the result is `None` if the values are
+    /// of incomparable types
+    pub fn compare(&self, other: &Value) -> Option<Option<Ordering>> {
+        match (self, other) {
+            (&Value::Null, _) | (_, &Value::Null) => Some(None),
+            (&Value::Integer(a), &Value::Integer(b)) => Some(Some(a.cmp(&b))),
+            (Value::Text(a), Value::Text(b)) => Some(Some(a.cmp(b))),
+            (Value::Blob(a), Value::Blob(b)) => Some(Some(a.cmp(b))),
+            (&Value::Integer(a), &Value::Float(b)) => Some(compare_exact(a, b)),
+            (&Value::Float(a), &Value::Integer(b)) => {
+                Some(compare_exact(b, a).map(Ordering::reverse))
+            }
+            _ => Some(self.number()?.partial_cmp(&other.number()?)),
+        }
+    }
+
+    /// Total order used for sorting. `NULL` comes first, followed by
+    /// numbers, text and then blobs
+    pub fn sort_cmp(&self, other: &Value) -> Ordering {
+        fn rank(v: &Value) -> u8 {
+            match *v {
+                Value::Null => 0,
+                Value::Integer(_) | Value::Float(_) => 1,
+                Value::Text(_) => 2,
+                Value::Blob(_) => 3,
+            }
+        }
+        rank(self)
+            .cmp(&rank(other))
+            .then_with(|| self.compare(other).and_then(|o| o).unwrap_or(Ordering::Equal))
+    }
+
+    /// Apply a prefix operator
+    pub fn unary(&self, op: UnaryOp) -> Result<Value, OpError> {
+        match (op, self) {
+            (UnaryOp::Not, _) => {
+                let t = self.truth().ok_or(OpError::Invalid)?;
+                Ok(Value::boolean(t.map(|b| !b)))
+            }
+            (_, &Value::Null) => Ok(Value::Null),
+            (UnaryOp::Plus, &Value::Integer(_)) | (UnaryOp::Plus, &Value::Float(_)) => {
+                Ok(self.clone())
+            }
+            (UnaryOp::Negate, &Value::Integer(i)) => {
+                i.checked_neg().map(Value::Integer).ok_or(OpError::Overflow)
+            }
+            (UnaryOp::Negate, &Value::Float(x)) => Ok(Value::Float(-x)),
+            _ => Err(OpError::Invalid),
+        }
+    }
+
+    /// Apply a binary operator
+    pub fn binary(&self, op: BinaryOp, other: &Value) -> Result<Value, OpError> {
+        let truth = |v: &Value| v.truth().ok_or(OpError::Invalid);
+        match op {
+            BinaryOp::And => Ok(Value::boolean(match (truth(self)?, truth(other)?) {
+                (Some(false), _) | (_, Some(false)) => Some(false),
+                (Some(true), Some(true)) => Some(true),
+                _ => None,
+            })),
+            BinaryOp::Or => Ok(Value::boolean(match (truth(self)?, truth(other)?) {
+                (Some(true), _) | (_, Some(true)) => Some(true),
+                (Some(false), Some(false)) => Some(false),
+                _ => None,
+            })),
+            BinaryOp::Equal
+            | BinaryOp::NotEqual
+            | BinaryOp::LessThan
+            | BinaryOp::LessThanOrEqual
+            | BinaryOp::GreaterThan
+            | BinaryOp::GreaterThanOrEqual => {
+                let ordering = self.compare(other).ok_or(OpError::Invalid)?;
+                Ok(Value::boolean(ordering.map(|o| match op {
+                    BinaryOp::Equal => o == Ordering::Equal,
+                    BinaryOp::NotEqual => o != Ordering::Equal,
+                    BinaryOp::LessThan => o == Ordering::Less,
+                    BinaryOp::LessThanOrEqual => o != Ordering::Greater,
+                    BinaryOp::GreaterThan => o == Ordering::Greater,
+                    _ => o != Ordering::Less,
+                })))
+            }
+            BinaryOp::Add | BinaryOp::Subtract | BinaryOp::Multiply | BinaryOp::Divide => {
+                match (self, other) {
+                    (&Value::Null, v) | (v, &Value::Null) => match *v {
+                        Value::Text(_) | Value::Blob(_) => Err(OpError::Invalid),
+                        _ => Ok(Value::Null),
+                    },
+                    (&Value::Integer(a), &Value::Integer(b)) => integer_arithmetic(op, a, b),
+                    _ => match (self.number(), other.number()) {
+                        (Some(a), Some(b)) => Ok(float_arithmetic(op, a, b)),
+                        _ => Err(OpError::Invalid),
+                    },
+                }
+            }
+            BinaryOp::Concat => match (self, other) {
+                (&Value::Null, _) | (_, &Value::Null) => Ok(Value::Null),
+                (Value::Blob(a), Value::Blob(b)) => Ok(Value::Blob([&a[..], &b[..]].concat())),
+                (&Value::Blob(_), _) | (_, &Value::Blob(_)) => Err(OpError::Invalid),
+                _ => Ok(Value::Text(format!("{}{}", self, other))),
+            },
+        }
+    }
+
+    /// Convert the value to another type. Floats are truncated to
+    /// integers, and text is parsed as a number.
+    /// `None` if the value has no representation in that type, such as
+    /// text that is not a number, or a blob that is not UTF-8 text.
+    /// `NULL` remains `NULL`
+    pub fn cast(&self, to: DataType) -> Option<Value> {
+        Some(match (self, to) {
+            (&Value::Null, _) => Value::Null,
+            (_, t) if self.data_type() == Some(t) => self.clone(),
+            (&Value::Integer(i), DataType::Float) => Value::Float(i as f64),
+            (&Value::Float(x), DataType::Integer) => {
+                // Rounded towards zero, so only the integer part must fit
+                let x = x.trunc();
+                if x.is_nan() || x < i64::MIN as f64 || x >= i64::MAX as f64 {
+                    return None;
+                }
+                Value::Integer(x as i64)
+            }
+            (Value::Text(s), DataType::Integer) => Value::Integer(s.trim().parse().ok()?),
+            (Value::Text(s), DataType::Float) => Value::Float(s.trim().parse().ok()?),
+            (Value::Blob(b), DataType::Text) => Value::Text(String::from_utf8(b.clone()).ok()?),
+            (&Value::Blob(_), _) => return None,
+            (_, DataType::Text) => Value::Text(self.to_string()),
+            (_, DataType::Blob) => Value::Blob(self.to_string().into_bytes()),
+            _ => return None,
+        })
+    }
+
+    /// Convert the value for storage in a column of type `to`. Only `NULL`
+    /// and values of that type are accepted, except that an `INTEGER` is
+    /// widened to `FLOAT`
+    pub fn coerce(&self, to: DataType) -> Option<Value> {
+        match (self, to) {
+            (&Value::Integer(_), DataType::Float) => self.cast(to),
+            _ if self.is_null() || self.data_type() == Some(to) => Some(self.clone()),
+            _ => None,
+        }
+    }
+}
+
+/// Compare an integer with a float without rounding the integer, which
+/// `as f64` would do above 2^53. `None` if the float is NaN
+fn compare_exact(i: i64, x: f64) -> Option<Ordering> {
+    // 2^63, the first float beyond the range of an i64
+    const LIMIT: f64 = 9_223_372_036_854_775_808.0;
+    if x.is_nan() {
+        None
+    } else if x >= LIMIT {
+        Some(Ordering::Less)
+    } else if x < -LIMIT {
+        Some(Ordering::Greater)
+    } else {
+        // The integer part of the float fits in an i64, so compare that
+        // first, and then the fraction
+        let whole = x.trunc();
+        match i.cmp(&(whole as i64)) {
+            Ordering::Equal => 0.0.partial_cmp(&(x - whole)),
+            o => Some(o),
+        }
+    }
+}
+
+/// Integer arithmetic, which fails if the result does not fit. Division by
+/// zero is `NULL`
+fn integer_arithmetic(op: BinaryOp, a: i64, b: i64) -> Result<Value, OpError> {
+    let result = match op {
+        BinaryOp::Add => a.checked_add(b),
+        BinaryOp::Subtract => a.checked_sub(b),
+        BinaryOp::Multiply => a.checked_mul(b),
+        _ if b == 0 => return Ok(Value::Null),
+        _ => a.checked_div(b),
+    };
+    result.map(Value::Integer).ok_or(OpError::Overflow)
+}
+
+fn float_arithmetic(op: BinaryOp, a: f64, b: f64) -> Value {
+    Value::Float(match op {
+        BinaryOp::Add => a + b,
+        BinaryOp::Subtract => a - b,
+        BinaryOp::Multiply => a * b,
+        _ if b == 0.0 => return Value::Null,
+        _ => a / b,
+    })
+}
+
+impl fmt::Display for Value {
+    /// Write the value for display in query results. Blobs are written
+    /// as hexadecimal
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Value::Null => write!(f, "NULL"),
+            Value::Integer(i) => write!(f, "{}", i),
+            Value::Float(x) => write_float(f, x),
+            Value::Text(ref s) => write!(f, "{}", s),
+            Value::Blob(ref b) => {
+                write!(f, "x'")?;
+                for byte in b {
+                    write!(f, "{:02x}", byte)?;
+                }
+                write!(f, "'")
+            }
+        }
+    }
+}
+
+impl From<i64> for Value {
+    fn from(i: i64) -> Value {
+        Value::Integer(i)
+    }
+}
+
+impl From<f64> for Value {
+    fn from(x: f64) -> Value {
+        Value::Float(x)
+    }
+}
+
+impl<'a> From<&'a str> for Value {
+    fn from(s: &'a str) -> Value {
+        Value::Text(s.into())
+    }
+}
+
+impl From<String> for Value {
+    fn from(s: String) -> Value {
+        Value::Text(s)
+    }
+}
+
+impl From<Vec<u8>> for Value {
+    fn from(b: Vec<u8>) -> Value {
+        Value::Blob(b)
+    }
+}
+
+impl<T: Into<Value>> From<Option<T>> for Value {
+    fn from(v: Option<T>) -> Value {
+        v.map_or(Value::Null, Into::into)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn blob(b: &[u8]) -> Value {
+        Value::Blob(b.to_vec())
+    }
+
+    #[test]
+    fn compare() {
+        let one = Value::Integer(1);
+        assert_eq!(one.compare(&Value::Float(1.5)), Some(Some(Ordering::Less)));
+        assert_eq!(one.compare(&Value::Null), Some(None));
+        assert_eq!(Value::Null.compare(&blob(b"a")), Some(None));
+        assert_eq!(blob(b"a").compare(&blob(b"b")), Some(Some(Ordering::Less)));
+        assert_eq!(Value::from("a").compare(&blob(b"a")), None);
+        assert_eq!(Value::from("1").compare(&one), None);
+
+        // Integers beyond 2^53 are not rounded to the nearest float
+        let cmp = |a: Value, b: f64| a.compare(&b.into()).and_then(|o| o);
+        let big = Value::Integer(9007199254740993);
+        assert_eq!(cmp(big.clone(), 9007199254740992.0), Some(Ordering::Greater));
+        assert_eq!(Value::Float(9007199254740992.0).compare(&big), Some(Some(Ordering::Less)));
+        assert_eq!(big.binary(BinaryOp::Equal, &9007199254740992.0.into()), Ok(0.into()));
+        assert_eq!(cmp(i64::MAX.into(), i64::MAX as f64), Some(Ordering::Less));
+        assert_eq!(cmp(i64::MIN.into(), i64::MIN as f64), Some(Ordering::Equal));
+        assert_eq!(cmp((-2).into(), -1.5), Some(Ordering::Less));
+        assert_eq!(cmp((-1).into(), -1.5), Some(Ordering::Greater));
+        assert_eq!(cmp(one.clone(), f64::NAN), None);
+
+        assert_eq!(one.binary(BinaryOp::NotEqual, &Value::Float(1.0)), Ok(0.into()));
+        assert_eq!(one.binary(BinaryOp::LessThanOrEqual, &Value::Null), Ok(Value::Null));
+        assert_eq!(blob(b"").binary(BinaryOp::GreaterThan, &1.0.into()), Err(OpError::Invalid));
+    }
+
+    #[test]
+    fn logic() {
+        let (t, f, n) = (Value::Integer(1), Value::Integer(0), Value::Null);
+        assert_eq!(n.binary(BinaryOp::And, &f), Ok(f.clone()));
+        assert_eq!(n.binary(BinaryOp::And, &t), Ok(Value::Null));
+        assert_eq!(n.binary(BinaryOp::Or, &t), Ok(t.clone()));
+        assert_eq!(n.binary(BinaryOp::Or, &f), Ok(Value::Null));
+        assert_eq!(n.unary(UnaryOp::Not), Ok(Value::Null));
+        assert_eq!(Value::Float(0.5).unary(UnaryOp::Not), Ok(f.clone()));
+        assert_eq!(Value::from("yes").binary(BinaryOp::Or, &t), Err(OpError::Invalid));
+    }
+
+    #[test]
+    fn arithmetic() {
+        let (two, half) = (Value::Integer(2), Value::Float(0.5));
+        assert_eq!(two.binary(BinaryOp::Multiply, &half), Ok(1.0.into()));
+        assert_eq!(two.binary(BinaryOp::Divide, &Value::Integer(0)), Ok(Value::Null));
+        assert_eq!(half.binary(BinaryOp::Divide, &0.0.into()), Ok(Value::Null));
+        assert_eq!(two.binary(BinaryOp::Subtract, &Value::Null), Ok(Value::Null));
+        assert_eq!(
+            Value::Integer(i64::MIN).binary(BinaryOp::Divide, &(-1).into()),
+            Err(OpError::Overflow)
+        );
+        assert_eq!(Value::Integer(i64::MIN).unary(UnaryOp::Negate), Err(OpError::Overflow));
+        assert_eq!(
+            Value::Integer(i64::MAX).binary(BinaryOp::Add, &1.into()),
+            Err(OpError::Overflow)
+        );
+        assert_eq!(two.binary(BinaryOp::Add, &"2".into()), Err(OpError::Invalid));
+        assert_eq!(Value::Null.binary(BinaryOp::Add, &"2".into()), Err(OpError::Invalid));
+        assert_eq!(blob(b"").unary(UnaryOp::Negate), Err(OpError::Invalid));
+    }
+
+    #[test]
+    fn concat() {
+        let text = Value::from("x = ");
+        assert_eq!(text.binary(BinaryOp::Concat, &1.5.into()), Ok("x = 1.5".into()));
+        assert_eq!(Value::Integer(1).binary(BinaryOp::Concat, &2.into()), Ok("12".into()));
+        assert_eq!(text.binary(BinaryOp::Concat, &Value::Null), Ok(Value::Null));
+        assert_eq!(blob(b"a").binary(BinaryOp::Concat, &blob(b"b")), Ok(blob(b"ab")));
+        assert_eq!(text.binary(BinaryOp::Concat, &blob(b"b")), Err(OpError::Invalid));
+    }
+
+    #[test]
+    fn cast() {
+        assert_eq!(Value::Float(-2.9).cast(DataType::Integer), Some(Value::Integer(-2)));
+        assert_eq!(Value::Float(1e19).cast(DataType::Integer), None);
+        assert_eq!(Value::Integer(3).cast(DataType::Float), Some(Value::Float(3.0)));
+        assert_eq!(Value::from(" 42 ").cast(DataType::Integer), Some(Value::Integer(42)));
+        assert_eq!(Value::from("4.5").cast(DataType::Float), Some(Value::Float(4.5)));
+        assert_eq!(Value::from("four").cast(DataType::Integer), None);
+        assert_eq!(Value::Float(4.0).cast(DataType::Text), Some("4.0".into()));
+        assert_eq!(Value::from("hi").cast(DataType::Blob), Some(blob(b"hi")));
+        assert_eq!(blob(b"hi").cast(DataType::Text), Some("hi".into()));
+        assert_eq!(blob(&[0xff]).cast(DataType::Text), None);
+        assert_eq!(blob(b"1").cast(DataType::Integer), None);
+        assert_eq!(Value::Null.cast(DataType::Blob), Some(Value::Null));
+
+        assert_eq!(Value::Integer(3).coerce(DataType::Float), Some(Value::Float(3.0)));
+        assert_eq!(Value::Float(3.0).coerce(DataType::Integer), None);
+        assert_eq!(Value::from("3").coerce(DataType::Integer), None);
+        assert_eq!(Value::Null.coerce(DataType::Text), Some(Value::Null));
+    }
+
+    #[test]
+    fn display() {
+        assert_eq!(Value::Null.to_string(), "NULL");
+        assert_eq!(Value::Float(2.0).to_string(), "2.0");
+        assert_eq!(blob(&[0, 0xab]).to_string(), "x'00ab'");
+        assert_eq!(Value::Null.type_name(), "NULL");
+        assert_eq!(blob(b"").type_name(), "BLOB");
+        assert_eq!(DataType::from_token(&Token::FLOAT), Some(DataType::Float));
+        assert_eq!(DataType::from_token(&Token::NULL), None);
+    }
+}
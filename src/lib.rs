@@ -1 +1,6 @@
+pub mod error;
+pub mod exec;
+pub mod storage;
 pub mod syntax;
+
+pub use error::{Error, Result};
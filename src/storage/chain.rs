@@ -0,0 +1,129 @@
+//! Byte strings stored across a linked list of pages
+//!
+//! Every page of a chain begins with a short header, and the rest of the
+//! page holds the next piece of the string:
+//!
+//! | Offset | Size | Contents                                      |
+//! |--------|------|-----------------------------------------------|
+//! | 0      | 4    | Next page of the chain, 0 if there is none    |
+//! | 4      | 4    | Number of bytes stored on this page           |
+//! | 8      | 4    | 1 if the string ends on this page, 0 if not   |
+//!
+//! A chain keeps its pages when a shorter string is written to it, so that
+//! they can be reused by a longer one later
+use std::io::{self, Read, Seek, Write};
+use super::pager::Pager;
+use super::{invalid_data, read_u32, PageId, PAGE_SIZE};
+
+/// Length of the header at the start of each page
+const HEADER_SIZE: usize = 12;
+
+/// Number of bytes stored on each page of a chain
+pub const CAPACITY: usize = PAGE_SIZE - HEADER_SIZE;
+
+/// Store `bytes` in the chain beginning at page `first`, which must
+/// already exist. Pages that are already part of the chain are reused and
+/// more are allocated as needed
+pub fn write<F>(pager: &mut Pager<F>, first: PageId, bytes: &[u8]) -> io::Result<()>
+where
+    F: Read + Write + Seek,
+{
+    let mut id = first;
+    let mut chunks = bytes.chunks(CAPACITY).peekable();
+    loop {
+        let chunk = chunks.next().unwrap_or(&[]);
+        let last = chunks.peek().is_none();
+        let next = match read_u32(&pager.page(id)?[..4]) {
+            0 if !last => pager.allocate()?,
+            next => next,
+        };
+        let page = pager.page_mut(id)?;
+        page[..4].copy_from_slice(&next.to_be_bytes());
+        page[4..8].copy_from_slice(&(chunk.len() as u32).to_be_bytes());
+        page[8..12].copy_from_slice(&u32::from(last).to_be_bytes());
+        page[HEADER_SIZE..HEADER_SIZE + chunk.len()].copy_from_slice(chunk);
+        if last {
+            return Ok(());
+        }
+        id = next;
+    }
+}
+
+/// Read the whole chain beginning at page `first`
+pub fn read<F>(pager: &mut Pager<F>, first: PageId) -> io::Result<Vec<u8>>
+where
+    F: Read + Write + Seek,
+{
+    let mut bytes = Vec::new();
+    let mut id = first;
+    // A chain cannot have more pages than the file, so this stops a cycle
+    for _ in 0..pager.page_count() {
+        let page = pager.page(id)?;
+        let len = read_u32(&page[4..]) as usize;
+        if len > CAPACITY {
+            return Err(invalid_data(format!("page {} claims to hold {} bytes", id, len)));
+        }
+        bytes.extend_from_slice(&page[HEADER_SIZE..HEADER_SIZE + len]);
+        if read_u32(&page[8..]) != 0 {
+            return Ok(bytes);
+        }
+        id = match read_u32(page) {
+            0 => return Err(invalid_data(format!("chain of pages ends early at page {}", id))),
+            next => next,
+        };
+    }
+    Err(invalid_data(format!("chain of pages from page {} has a cycle", first)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn create() -> (Pager<Cursor<Vec<u8>>>, PageId) {
+        let mut pager = Pager::create(Cursor::new(Vec::new()), 2).unwrap();
+        let first = pager.allocate().unwrap();
+        write(&mut pager, first, &[]).unwrap();
+        (pager, first)
+    }
+
+    #[test]
+    fn round_trip() {
+        let (mut pager, first) = create();
+        assert_eq!(read(&mut pager, first).unwrap(), Vec::<u8>::new());
+
+        let long: Vec<u8> = (0..CAPACITY * 2 + 10).map(|i| i as u8).collect();
+        write(&mut pager, first, &long).unwrap();
+        assert_eq!(pager.page_count(), 4);
+        let mut pager = Pager::open(pager.into_inner().unwrap(), 1).unwrap();
+        assert_eq!(read(&mut pager, first).unwrap(), long);
+
+        // A shorter string reuses the pages of the chain
+        write(&mut pager, first, b"short").unwrap();
+        assert_eq!(read(&mut pager, first).unwrap(), b"short");
+        write(&mut pager, first, &long).unwrap();
+        assert_eq!(pager.page_count(), 4);
+        assert_eq!(read(&mut pager, first).unwrap(), long);
+    }
+
+    #[test]
+    fn invalid_chains() {
+        let (mut pager, first) = create();
+        let mut error = |header: &[u8]| {
+            pager.page_mut(first).unwrap()[..12].copy_from_slice(header);
+            read(&mut pager, first).unwrap_err().to_string()
+        };
+        assert_eq!(
+            error(&[0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 0]),
+            "chain of pages from page 1 has a cycle"
+        );
+        assert_eq!(
+            error(&[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]),
+            "chain of pages ends early at page 1"
+        );
+        assert_eq!(
+            error(&[0, 0, 0, 0, 0, 0, 16, 0, 0, 0, 0, 1]),
+            "page 1 claims to hold 4096 bytes"
+        );
+    }
+}
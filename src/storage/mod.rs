@@ -0,0 +1,46 @@
+//! Page-based storage in a single file
+//!
+//! A database file is a sequence of fixed-size pages. The first page holds
+//! the file header, and every other page is read and written through a
+//! bounded buffer pool:
+//!
+//! | Offset | Size | Contents                            |
+//! |--------|------|-------------------------------------|
+//! | 0      | 8    | Magic number, `SHARDDB\0`           |
+//! | 8      | 4    | Format version                      |
+//! | 12     | 4    | Page size in bytes                  |
+//! | 16     | 4    | Number of pages, including this one |
+//!
+//! Integers are stored big-endian, and the rest of the header page is
+//! reserved. Data longer than a page is kept in a `chain` of pages
+
+pub mod chain;
+pub mod pager;
+pub mod pool;
+
+use std::io;
+
+pub use self::pager::Pager;
+
+/// Size of every page, in bytes
+pub const PAGE_SIZE: usize = 4096;
+
+/// First bytes of every database file
+pub const MAGIC: [u8; 8] = *b"SHARDDB\0";
+
+/// Version of the file format written by this implementation. Files of
+/// any other version are refused
+pub const FORMAT_VERSION: u32 = 1;
+
+/// Position of a page in the file. Page 0 is the header
+pub type PageId = u32;
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn read_u32(bytes: &[u8]) -> u32 {
+    let mut b = [0u8; 4];
+    b.copy_from_slice(&bytes[..4]);
+    u32::from_be_bytes(b)
+}
@@ -0,0 +1,299 @@
+//! Reading and writing the pages of a database file
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use super::pool::BufferPool;
+use super::{invalid_data, read_u32, PageId, FORMAT_VERSION, MAGIC, PAGE_SIZE};
+
+/// Length of the header fields at the start of page 0
+const HEADER_SIZE: usize = 20;
+
+/// Reads and writes the pages of a database file through a buffer pool
+///
+/// Modified pages are written back when they are evicted from the pool,
+/// or when the pager is flushed. Changes that were never flushed are lost
+/// when the pager is dropped
+pub struct Pager<F> {
+    file: F,
+    pool: BufferPool,
+    /// Number of pages, including the header
+    page_count: u32,
+    /// The page count has changed since the header was last written
+    header_dirty: bool,
+}
+
+/// Write a page to its place in the file
+fn write_page<F: Write + Seek>(file: &mut F, id: PageId, data: &[u8]) -> io::Result<()> {
+    file.seek(SeekFrom::Start(u64::from(id) * PAGE_SIZE as u64))?;
+    file.write_all(data)
+}
+
+impl<F: Read + Write + Seek> Pager<F> {
+    /// Start a new database in `file`, caching at most `capacity` pages
+    pub fn create(file: F, capacity: usize) -> io::Result<Pager<F>> {
+        let mut pager = Pager {
+            file,
+            pool: BufferPool::new(capacity),
+            page_count: 1,
+            header_dirty: true,
+        };
+        pager.flush()?;
+        Ok(pager)
+    }
+
+    /// Open an existing database in `file`, caching at most `capacity`
+    /// pages. Fails if the file does not begin with a valid header
+    pub fn open(mut file: F, capacity: usize) -> io::Result<Pager<F>> {
+        let mut header = [0u8; HEADER_SIZE];
+        file.seek(SeekFrom::Start(0))?;
+        file.read_exact(&mut header).map_err(|e| match e.kind() {
+            io::ErrorKind::UnexpectedEof => {
+                invalid_data("file is too short to be a database".into())
+            }
+            _ => e,
+        })?;
+        if header[..8] != MAGIC {
+            return Err(invalid_data("file is not a shard database".into()));
+        }
+        let version = read_u32(&header[8..]);
+        if version != FORMAT_VERSION {
+            return Err(invalid_data(format!("unsupported format version {}", version)));
+        }
+        let page_size = read_u32(&header[12..]);
+        if page_size as usize != PAGE_SIZE {
+            return Err(invalid_data(format!("unsupported page size {}", page_size)));
+        }
+        let page_count = read_u32(&header[16..]);
+        if page_count == 0 {
+            return Err(invalid_data("header counts no pages".into()));
+        }
+        Ok(Pager {
+            file,
+            pool: BufferPool::new(capacity),
+            page_count,
+            header_dirty: false,
+        })
+    }
+
+    /// Number of pages in the database, including the header
+    pub fn page_count(&self) -> u32 {
+        self.page_count
+    }
+
+    /// The buffer pool, to inspect which pages are cached
+    pub fn pool(&self) -> &BufferPool {
+        &self.pool
+    }
+
+    /// Add a zeroed page to the end of the database
+    pub fn allocate(&mut self) -> io::Result<PageId> {
+        let id = self.page_count;
+        self.cache(id, vec![0; PAGE_SIZE].into_boxed_slice(), true)?;
+        self.page_count += 1;
+        self.header_dirty = true;
+        Ok(id)
+    }
+
+    /// Contents of a page
+    pub fn page(&mut self, id: PageId) -> io::Result<&[u8]> {
+        self.load(id)?;
+        Ok(self.pool.get(id).expect("Page was not loaded"))
+    }
+
+    /// Contents of a page to modify. The page is written back when it is
+    /// evicted or flushed
+    pub fn page_mut(&mut self, id: PageId) -> io::Result<&mut [u8]> {
+        self.load(id)?;
+        Ok(self.pool.get_mut(id).expect("Page was not loaded"))
+    }
+
+    /// Write every modified page and the header to the file
+    pub fn flush(&mut self) -> io::Result<()> {
+        {
+            let file = &mut self.file;
+            self.pool.flush(|id, data| write_page(file, id, data))?;
+        }
+        // The header is written last, so that it never counts pages that
+        // are not yet in the file
+        if self.header_dirty {
+            let mut header = vec![0u8; PAGE_SIZE];
+            header[..8].copy_from_slice(&MAGIC);
+            header[8..12].copy_from_slice(&FORMAT_VERSION.to_be_bytes());
+            header[12..16].copy_from_slice(&(PAGE_SIZE as u32).to_be_bytes());
+            header[16..20].copy_from_slice(&self.page_count.to_be_bytes());
+            write_page(&mut self.file, 0, &header)?;
+            self.header_dirty = false;
+        }
+        self.file.flush()
+    }
+
+    /// Flush the database, and return the underlying file
+    pub fn into_inner(mut self) -> io::Result<F> {
+        self.flush()?;
+        Ok(self.file)
+    }
+
+    /// Make sure that a page is in the buffer pool
+    fn load(&mut self, id: PageId) -> io::Result<()> {
+        if id == 0 || id >= self.page_count {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("page {} is out of bounds", id),
+            ));
+        }
+        if !self.pool.contains(id) {
+            let mut data = vec![0; PAGE_SIZE].into_boxed_slice();
+            self.file.seek(SeekFrom::Start(u64::from(id) * PAGE_SIZE as u64))?;
+            self.file.read_exact(&mut data)?;
+            self.cache(id, data, false)?;
+        }
+        Ok(())
+    }
+
+    fn cache(&mut self, id: PageId, data: Box<[u8]>, dirty: bool) -> io::Result<()> {
+        let file = &mut self.file;
+        self.pool.insert(id, data, dirty, |victim, data| write_page(file, victim, data))
+    }
+}
+
+impl Pager<File> {
+    /// Create a database file, which must not already exist
+    pub fn create_file<P: AsRef<Path>>(path: P, capacity: usize) -> io::Result<Pager<File>> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .open(path)?;
+        Pager::create(file, capacity)
+    }
+
+    /// Open an existing database file
+    pub fn open_file<P: AsRef<Path>>(path: P, capacity: usize) -> io::Result<Pager<File>> {
+        let file = OpenOptions::new().read(true).write(true).open(path)?;
+        Pager::open(file, capacity)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::io::Cursor;
+
+    fn create(capacity: usize) -> Pager<Cursor<Vec<u8>>> {
+        Pager::create(Cursor::new(Vec::new()), capacity).unwrap()
+    }
+
+    fn reopen(pager: Pager<Cursor<Vec<u8>>>, capacity: usize) -> Pager<Cursor<Vec<u8>>> {
+        Pager::open(pager.into_inner().unwrap(), capacity).unwrap()
+    }
+
+    #[test]
+    fn header() {
+        let bytes = create(1).into_inner().unwrap().into_inner();
+        assert_eq!(bytes.len(), PAGE_SIZE);
+        assert_eq!(&bytes[..8], b"SHARDDB\0");
+        assert_eq!(&bytes[8..20], &[0, 0, 0, 1, 0, 0, 16, 0, 0, 0, 0, 1]);
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut pager = create(2);
+        for i in 1..=5u8 {
+            let id = pager.allocate().unwrap();
+            assert_eq!(id, u32::from(i));
+            pager.page_mut(id).unwrap()[..3].copy_from_slice(&[i, i, i]);
+        }
+        // Only two pages fit in the pool, so the rest were written back
+        assert_eq!(pager.pool().len(), 2);
+        assert_eq!(pager.page(1).unwrap()[..4], [1, 1, 1, 0]);
+
+        let mut pager = reopen(pager, 3);
+        assert_eq!(pager.page_count(), 6);
+        for i in 1..=5u8 {
+            assert_eq!(pager.page(u32::from(i)).unwrap()[..4], [i, i, i, 0]);
+        }
+        assert_eq!(pager.pool().len(), 3);
+    }
+
+    #[test]
+    fn dirty_tracking() {
+        let mut pager = reopen(
+            {
+                let mut pager = create(4);
+                pager.allocate().unwrap();
+                pager.allocate().unwrap();
+                pager
+            },
+            4,
+        );
+        pager.page(1).unwrap();
+        pager.page_mut(2).unwrap()[0] = 42;
+        assert!(!pager.pool().is_dirty(1) && pager.pool().is_dirty(2));
+        pager.flush().unwrap();
+        assert!(!pager.pool().is_dirty(2));
+        assert_eq!(reopen(pager, 1).page(2).unwrap()[0], 42);
+    }
+
+    #[test]
+    fn invalid_files() {
+        let error = |bytes: Vec<u8>| {
+            Pager::open(Cursor::new(bytes), 1)
+                .err()
+                .map(|e| (e.kind(), e.to_string()))
+        };
+        let valid = create(1).into_inner().unwrap().into_inner();
+
+        assert_eq!(
+            error(Vec::new()),
+            Some((io::ErrorKind::InvalidData, "file is too short to be a database".into()))
+        );
+        let mut bytes = valid.clone();
+        bytes[0] = b's';
+        assert_eq!(
+            error(bytes),
+            Some((io::ErrorKind::InvalidData, "file is not a shard database".into()))
+        );
+        let mut bytes = valid.clone();
+        bytes[11] = 2;
+        assert_eq!(
+            error(bytes),
+            Some((io::ErrorKind::InvalidData, "unsupported format version 2".into()))
+        );
+        let mut bytes = valid;
+        bytes[14] = 0x20;
+        assert_eq!(
+            error(bytes),
+            Some((io::ErrorKind::InvalidData, "unsupported page size 8192".into()))
+        );
+    }
+
+    #[test]
+    fn out_of_bounds() {
+        let mut pager = create(1);
+        pager.allocate().unwrap();
+        assert!(pager.page(1).is_ok());
+        for &id in &[0, 2] {
+            let error = pager.page(id).unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+            assert_eq!(error.to_string(), format!("page {} is out of bounds", id));
+        }
+    }
+
+    #[test]
+    fn file() {
+        let path = std::env::temp_dir().join(format!("shard-pager-{}.db", std::process::id()));
+        let _ = fs::remove_file(&path);
+        {
+            let mut pager = Pager::create_file(&path, 2).unwrap();
+            let id = pager.allocate().unwrap();
+            pager.page_mut(id).unwrap()[PAGE_SIZE - 1] = 7;
+            pager.flush().unwrap();
+        }
+        assert!(Pager::create_file(&path, 2).is_err());
+        let mut pager = Pager::open_file(&path, 2).unwrap();
+        assert_eq!(pager.page(1).unwrap()[PAGE_SIZE - 1], 7);
+        assert_eq!(fs::metadata(&path).unwrap().len(), 2 * PAGE_SIZE as u64);
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,190 @@
+//! A bounded cache of pages, evicting the least recently used page
+use std::collections::HashMap;
+use std::io;
+use super::PageId;
+
+/// A cached page
+struct Frame {
+    data: Box<[u8]>,
+    /// Modified since it was last written back
+    dirty: bool,
+    /// Tick of the most recent access
+    used: u64,
+}
+
+/// Holds at most `capacity` pages in memory, tracking which have been
+/// modified. A dirty page is handed back for writing before it is evicted
+pub struct BufferPool {
+    capacity: usize,
+    frames: HashMap<PageId, Frame>,
+    tick: u64,
+}
+
+impl BufferPool {
+    pub fn new(capacity: usize) -> BufferPool {
+        assert!(capacity > 0, "Buffer pool must hold at least one page");
+        BufferPool {
+            capacity,
+            frames: HashMap::with_capacity(capacity),
+            tick: 0,
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Number of pages currently cached
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    pub fn contains(&self, id: PageId) -> bool {
+        self.frames.contains_key(&id)
+    }
+
+    pub fn is_dirty(&self, id: PageId) -> bool {
+        self.frames.get(&id).is_some_and(|f| f.dirty)
+    }
+
+    /// Look up a cached page, marking it as the most recently used
+    pub fn get(&mut self, id: PageId) -> Option<&[u8]> {
+        self.touch(id).map(|f| &*f.data)
+    }
+
+    /// Look up a cached page to modify, marking it as dirty and the most
+    /// recently used
+    pub fn get_mut(&mut self, id: PageId) -> Option<&mut [u8]> {
+        self.touch(id).map(|f| {
+            f.dirty = true;
+            &mut *f.data
+        })
+    }
+
+    /// Cache a page. If the pool is full, the least recently used page is
+    /// evicted first, and passed to `write_back` if it is dirty. The page
+    /// stays cached if it could not be written back
+    pub fn insert<F>(
+        &mut self,
+        id: PageId,
+        data: Box<[u8]>,
+        dirty: bool,
+        write_back: F,
+    ) -> io::Result<()>
+    where
+        F: FnOnce(PageId, &[u8]) -> io::Result<()>,
+    {
+        if !self.contains(id) && self.frames.len() >= self.capacity {
+            let (&victim, frame) = self
+                .frames
+                .iter()
+                .min_by_key(|&(_, f)| f.used)
+                .expect("Buffer pool is empty");
+            if frame.dirty {
+                write_back(victim, &frame.data)?;
+            }
+            self.frames.remove(&victim);
+        }
+        self.tick += 1;
+        self.frames.insert(
+            id,
+            Frame {
+                data,
+                dirty,
+                used: self.tick,
+            },
+        );
+        Ok(())
+    }
+
+    /// Pass every dirty page to `write`, in page order, marking each clean
+    /// once it has been written
+    pub fn flush<F>(&mut self, mut write: F) -> io::Result<()>
+    where
+        F: FnMut(PageId, &[u8]) -> io::Result<()>,
+    {
+        let mut dirty: Vec<PageId> = self
+            .frames
+            .iter()
+            .filter(|&(_, f)| f.dirty)
+            .map(|(&id, _)| id)
+            .collect();
+        dirty.sort_unstable();
+        for id in dirty {
+            let frame = self.frames.get_mut(&id).expect("Dirty page is not cached");
+            write(id, &frame.data)?;
+            frame.dirty = false;
+        }
+        Ok(())
+    }
+
+    fn touch(&mut self, id: PageId) -> Option<&mut Frame> {
+        self.tick += 1;
+        let frame = self.frames.get_mut(&id)?;
+        frame.used = self.tick;
+        Some(frame)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn page(byte: u8) -> Box<[u8]> {
+        vec![byte; 4].into_boxed_slice()
+    }
+
+    #[test]
+    fn evict_least_recently_used() {
+        let mut pool = BufferPool::new(2);
+        let mut written = Vec::new();
+        pool.insert(1, page(1), false, |_, _| unreachable!()).unwrap();
+        pool.insert(2, page(2), true, |_, _| unreachable!()).unwrap();
+        // Page 2 is now the least recently used, and is dirty
+        assert_eq!(pool.get(1), Some(&[1u8; 4][..]));
+        pool.insert(3, page(3), false, |id, data| {
+            written.push((id, data.to_vec()));
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(written, vec![(2, vec![2; 4])]);
+        assert!(pool.contains(1) && !pool.contains(2) && pool.contains(3));
+
+        // Clean pages are dropped without being written
+        pool.insert(4, page(4), false, |_, _| unreachable!()).unwrap();
+        assert!(!pool.contains(1));
+        assert_eq!(pool.len(), 2);
+    }
+
+    #[test]
+    fn failed_write_back() {
+        let mut pool = BufferPool::new(1);
+        pool.insert(1, page(1), true, |_, _| unreachable!()).unwrap();
+        let error = pool.insert(2, page(2), false, |_, _| Err(io::ErrorKind::Other.into()));
+        assert!(error.is_err());
+        assert!(pool.contains(1) && pool.is_dirty(1) && !pool.contains(2));
+    }
+
+    #[test]
+    fn flush() {
+        let mut pool = BufferPool::new(4);
+        for id in (1..4).rev() {
+            pool.insert(id, page(0), false, |_, _| unreachable!()).unwrap();
+        }
+        pool.get_mut(3).unwrap()[0] = 9;
+        pool.get_mut(1).unwrap()[0] = 7;
+        let mut written = Vec::new();
+        pool.flush(|id, data| {
+            written.push((id, data[0]));
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(written, vec![(1, 7), (3, 9)]);
+        assert!(!pool.is_dirty(1) && !pool.is_dirty(3));
+        pool.flush(|_, _| unreachable!()).unwrap();
+    }
+}
@@ -0,0 +1,281 @@
+//! Binding values to the placeholders of prepared statements
+//!
+//! `?` placeholders are numbered in the order they appear in the statement,
+//! starting from 1, and share their numbering with `$n` placeholders. Named
+//! `:name` placeholders are bound by name. Binding replaces each placeholder
+//! with a literal, so values are never spliced into the query text
+use std::borrow::Cow;
+use error::{Error, Result};
+use super::*;
+use super::expr::{Expr, ExprKind};
+use super::statement::Statement;
+use super::visit::{walk_expr_mut, VisitorMut};
+use super::super::token::Placeholder;
+
+/// A typed value to bind to a placeholder
+#[derive(Debug, Clone, PartialEq)]
+pub enum Param<'a> {
+    Null,
+    Integer(i64),
+    Float(f64),
+    Text(Cow<'a, str>),
+}
+
+impl<'a> Param<'a> {
+    /// Literal token with the same value. A float must be finite, as NaN
+    /// and the infinities have no literal
+    fn to_token(&self) -> Token<'a> {
+        match *self {
+            Param::Null => Token::NULL,
+            Param::Integer(i) => Token::IntegerLiteral(i),
+            Param::Float(x) => Token::FloatLiteral(x),
+            Param::Text(ref s) => Token::StringLiteral(s.clone()),
+        }
+    }
+}
+
+impl<'a> From<i64> for Param<'a> {
+    fn from(i: i64) -> Param<'a> {
+        Param::Integer(i)
+    }
+}
+
+impl<'a> From<i32> for Param<'a> {
+    fn from(i: i32) -> Param<'a> {
+        Param::Integer(i64::from(i))
+    }
+}
+
+impl<'a> From<f64> for Param<'a> {
+    fn from(x: f64) -> Param<'a> {
+        Param::Float(x)
+    }
+}
+
+impl<'a> From<&'a str> for Param<'a> {
+    fn from(s: &'a str) -> Param<'a> {
+        Param::Text(Cow::Borrowed(s))
+    }
+}
+
+impl<'a> From<String> for Param<'a> {
+    fn from(s: String) -> Param<'a> {
+        Param::Text(Cow::Owned(s))
+    }
+}
+
+/// `None` is bound as `NULL`
+impl<'a, T: Into<Param<'a>>> From<Option<T>> for Param<'a> {
+    fn from(value: Option<T>) -> Param<'a> {
+        value.map_or(Param::Null, Into::into)
+    }
+}
+
+impl<'a> Statement<'a> {
+    /// Bind `params` to the `?` and `$n` placeholders of the statement, by
+    /// position. It is an error for any placeholder to be left unbound, or
+    /// to be bound to a float that is not finite
+    pub fn bind(&mut self, params: &[Param<'a>]) -> Result<()> {
+        let mut anonymous = 0;
+        self.bind_with(|placeholder| {
+            let index = match *placeholder {
+                Placeholder::Anonymous => {
+                    anonymous += 1;
+                    anonymous
+                }
+                Placeholder::Index(i) => i,
+                Placeholder::Named(_) => return None,
+            };
+            index.checked_sub(1).and_then(|i| params.get(i))
+        })
+    }
+
+    /// Bind `params` to the `:name` placeholders of the statement, by name.
+    /// It is an error for any placeholder to be left unbound, or to be
+    /// bound to a float that is not finite
+    pub fn bind_named(&mut self, params: &[(&str, Param<'a>)]) -> Result<()> {
+        self.bind_with(|placeholder| match *placeholder {
+            Placeholder::Named(ref name) => params
+                .iter()
+                .find(|param| param.0 == name)
+                .map(|param| &param.1),
+            _ => None,
+        })
+    }
+
+    fn bind_with<'p, F>(&mut self, lookup: F) -> Result<()>
+    where
+        F: FnMut(&Placeholder<'a>) -> Option<&'p Param<'a>>,
+        'a: 'p,
+    {
+        let mut bind = Bind { lookup, error: None };
+        bind.visit_statement_mut(self);
+        bind.error.map_or(Ok(()), Err)
+    }
+}
+
+/// Replaces each placeholder with the literal value found by `lookup`,
+/// stopping at the first placeholder without a value or literal
+struct Bind<F> {
+    lookup: F,
+    error: Option<Error>,
+}
+
+impl<'a, 'p, F> VisitorMut<'a> for Bind<F>
+where
+    F: FnMut(&Placeholder<'a>) -> Option<&'p Param<'a>>,
+    'a: 'p,
+{
+    fn visit_expr_mut(&mut self, expr: &mut Expr<'a>) {
+        if self.error.is_some() {
+            return;
+        }
+        let literal = match expr.kind {
+            ExprKind::Placeholder(ref placeholder) => match (self.lookup)(placeholder) {
+                Some(&Param::Float(value)) if !value.is_finite() => {
+                    self.error = Some(Error::NonFiniteParam {
+                        placeholder: placeholder.clone().into_owned(),
+                        value,
+                        span: expr.span,
+                    });
+                    return;
+                }
+                Some(param) => param.to_token(),
+                None => {
+                    self.error = Some(Error::UnboundPlaceholder {
+                        placeholder: placeholder.clone().into_owned(),
+                        span: expr.span,
+                    });
+                    return;
+                }
+            },
+            _ => return walk_expr_mut(self, expr),
+        };
+        expr.kind = ExprKind::Literal(literal);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::super::parse_script;
+    use super::super::columns::Column;
+    use super::super::select::Select;
+
+    fn parse<'a>(s: &'a str) -> Statement<'a> {
+        parse_script(s).unwrap().remove(0)
+    }
+
+    /// Literal values of the select columns
+    fn columns<'a>(select: &Select<'a>) -> Vec<Token<'a>> {
+        select
+            .columns
+            .iter()
+            .map(|column| match *column {
+                Column::Expr(
+                    Expr {
+                        kind: ExprKind::Literal(ref tok),
+                        ..
+                    },
+                    _,
+                ) => tok.clone(),
+                _ => panic!("Expected literal, found {:?}", column),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn bind_positional() {
+        let mut statement = parse("select ?, $1, ?, $3 from t");
+        statement
+            .bind(&["it's".into(), 2.into(), Param::from(None::<i64>)])
+            .unwrap();
+        match statement {
+            Statement::Select(ref select) => assert_eq!(
+                columns(select),
+                vec![
+                    Token::StringLiteral("it's".into()),
+                    Token::StringLiteral("it's".into()),
+                    Token::IntegerLiteral(2),
+                    Token::NULL,
+                ]
+            ),
+            _ => panic!("Expected select"),
+        }
+    }
+
+    #[test]
+    fn bind_named() {
+        let mut statement = parse("insert into t values (:id, :Name), (:id, 1.5)");
+        statement
+            .bind_named(&[("Name", "bob".into()), ("id", 7.into())])
+            .unwrap();
+        match statement {
+            Statement::Insert(ref insert) => {
+                let rows: Vec<Vec<&ExprKind>> = insert
+                    .rows
+                    .iter()
+                    .map(|row| row.iter().map(|e| &e.kind).collect())
+                    .collect();
+                assert_eq!(
+                    rows,
+                    vec![
+                        vec![
+                            &ExprKind::Literal(Token::IntegerLiteral(7)),
+                            &ExprKind::Literal(Token::StringLiteral("bob".into())),
+                        ],
+                        vec![
+                            &ExprKind::Literal(Token::IntegerLiteral(7)),
+                            &ExprKind::Literal(Token::FloatLiteral(1.5)),
+                        ],
+                    ]
+                );
+            }
+            _ => panic!("Expected insert"),
+        }
+    }
+
+    #[test]
+    fn bind_unbound() {
+        let s = "select a from t where a = ? and b = $3";
+        let mut statement = parse(s);
+        match statement.bind(&[1.into(), 2.into()]) {
+            Err(Error::UnboundPlaceholder { placeholder, span }) => {
+                assert_eq!(placeholder, Placeholder::Index(3));
+                assert_eq!(&s[span.start..span.end], "$3");
+            }
+            r => panic!("Expected unbound placeholder, found {:?}", r),
+        }
+
+        // `$0` is rejected by the lexer, but may still be built by hand
+        let mut statement = parse("select $1 from t");
+        if let Statement::Select(ref mut select) = statement {
+            if let Column::Expr(ref mut expr, _) = select.columns[0] {
+                expr.kind = ExprKind::Placeholder(Placeholder::Index(0));
+            }
+        }
+        assert!(statement.bind(&[1.into()]).is_err());
+
+        // Named placeholders are not bound by position, and names are case
+        // sensitive
+        let mut statement = parse("select :a from t");
+        assert!(statement.bind(&[1.into()]).is_err());
+        assert!(statement.bind_named(&[("A", 1.into())]).is_err());
+    }
+
+    #[test]
+    fn bind_non_finite() {
+        for &x in &[f64::NAN, f64::INFINITY, f64::NEG_INFINITY] {
+            let mut statement = parse("select a from t where a = $1");
+            match statement.bind(&[x.into()]) {
+                Err(Error::NonFiniteParam { placeholder, .. }) => {
+                    assert_eq!(placeholder, Placeholder::Index(1))
+                }
+                r => panic!("Expected non-finite error for {}, found {:?}", x, r),
+            }
+            let mut statement = parse("select :x from t");
+            let error = statement.bind_named(&[("x", x.into())]).unwrap_err();
+            assert_eq!(error.message(), format!("cannot bind `{}` to placeholder `:x`", x));
+        }
+    }
+}
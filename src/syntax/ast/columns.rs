@@ -1,19 +1,159 @@
+use std::fmt;
 use super::*;
+use super::expr::{Expr, ExprKind};
 
-#[derive(Debug)]
-pub enum Column {
-    All,
-    Expr(Token),
+/// A single term of a projection
+#[derive(Debug, Clone, PartialEq)]
+pub enum Column<'a> {
+    /// `*`
+    All(Span),
+    /// `table.*`
+    AllFrom(Token<'a>, Span),
+    /// An expression, with an alias given by `expr AS alias` or `expr alias`
+    Expr(Expr<'a>, Option<Token<'a>>),
 }
 
-impl Syntax for Column {
-    type Output = Column;
-    fn parse(parser: &mut Parser) -> ParserResult<Self::Output> {
+impl<'a> Column<'a> {
+    pub fn span(&self) -> Span {
+        match *self {
+            Column::All(span) | Column::AllFrom(_, span) => span,
+            Column::Expr(ref expr, _) => expr.span,
+        }
+    }
+
+    /// Name of the result column: the alias if one was given, otherwise
+    /// the name of the referenced column, or else the expression as SQL.
+    /// Wildcards stand for many columns and have no name of their own
+    pub fn name(&self) -> Option<String> {
+        match *self {
+            Column::All(_) | Column::AllFrom(..) => None,
+            Column::Expr(_, Some(ref alias)) => alias.name().map(String::from),
+            Column::Expr(ref expr, None) => match expr.kind {
+                ExprKind::Column(ref column) | ExprKind::QualifiedColumn(_, ref column) => {
+                    column.name().map(String::from)
+                }
+                _ => Some(expr.to_string()),
+            },
+        }
+    }
+}
+
+impl<'a> fmt::Display for Column<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Column::All(_) => write!(f, "*"),
+            Column::AllFrom(ref table, _) => write!(f, "{}.*", table),
+            Column::Expr(ref expr, None) => write!(f, "{}", expr),
+            Column::Expr(ref expr, Some(ref alias)) => write!(f, "{} AS {}", expr, alias),
+        }
+    }
+}
+
+impl<'a> Syntax<'a> for Column<'a> {
+    type Output = Column<'a>;
+    fn parse(parser: &mut Parser<'a>) -> ParserResult<Self::Output> {
+        let start = parser.peek_span();
         if parser.pop_if(&Token::ASTERISK) {
-            Ok(Column::All)
-        } else {
-            let column = parser.pop()?;
-            Ok(Column::Expr(column))
+            return Ok(Column::All(start));
         }
+
+        let expr = match parser.peek() {
+            // `table.*` is only told apart from `table.column` after the
+            // dot, so column references are parsed here rather than by
+            // the expression parser
+            Some(&Token::Identifier(_)) | Some(&Token::QuotedIdentifier(_)) => {
+                let name = parser.pop()?;
+                let kind = if parser.pop_if(&Token::DOT) {
+                    if parser.pop_if(&Token::ASTERISK) {
+                        return Ok(Column::AllFrom(name, parser.span_from(start)));
+                    }
+                    ExprKind::QualifiedColumn(name, parser.expect_identifier()?)
+                } else {
+                    ExprKind::Column(name)
+                };
+                let lhs = Expr {
+                    kind,
+                    span: parser.span_from(start),
+                };
+                Expr::parse_infix(parser, lhs, 0)?
+            }
+            _ => Expr::parse(parser)?,
+        };
+
+        let alias = match parser.peek() {
+            Some(&Token::AS) => {
+                parser.pop()?;
+                Some(parser.expect_identifier()?)
+            }
+            Some(&Token::Identifier(_)) | Some(&Token::QuotedIdentifier(_)) => Some(parser.pop()?),
+            _ => None,
+        };
+        Ok(Column::Expr(expr, alias))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::super::lexer::Lexer;
+    use super::super::expr::BinaryOp;
+
+    fn column<'a>(s: &'a str) -> Column<'a> {
+        let mut parser = Lexer::lex(s).unwrap();
+        let column = Column::parse(&mut parser).unwrap();
+        assert_eq!(parser.peek(), None);
+        column
+    }
+
+    #[test]
+    fn qualified_all() {
+        assert_eq!(
+            column("users.*"),
+            Column::AllFrom(Token::Identifier("users".into()), Span::new(0, 7, 1, 1))
+        );
+        assert_eq!(column("\"Users\" . *").to_string(), "\"Users\".*");
+    }
+
+    #[test]
+    fn alias() {
+        match column("t.id as user_id") {
+            Column::Expr(expr, alias) => {
+                assert_eq!(
+                    expr.kind,
+                    ExprKind::QualifiedColumn(
+                        Token::Identifier("t".into()),
+                        Token::Identifier("id".into()),
+                    )
+                );
+                assert_eq!(alias, Some(Token::Identifier("user_id".into())));
+            }
+            c => panic!("Expected aliased column, found {:?}", c),
+        }
+
+        // An identifier directly after the expression is an implicit alias
+        match column("a + b total") {
+            Column::Expr(expr, alias) => {
+                match expr.kind {
+                    ExprKind::Binary(_, BinaryOp::Add, _) => (),
+                    k => panic!("Expected `a + b`, found {:?}", k),
+                }
+                assert_eq!(alias, Some(Token::Identifier("total".into())));
+            }
+            c => panic!("Expected aliased column, found {:?}", c),
+        }
+
+        // The operator loop carries on after a leading column reference
+        assert_eq!(column("t.a * 2 + 1 AS \"X\"").to_string(), "t.a * 2 + 1 AS \"X\"");
+    }
+
+    #[test]
+    fn name() {
+        assert_eq!(column("t.id AS user_id").name(), Some("user_id".into()));
+        assert_eq!(column("t.id \"UserId\"").name(), Some("UserId".into()));
+        assert_eq!(column("t.id").name(), Some("id".into()));
+        assert_eq!(column("Name").name(), Some("name".into()));
+        assert_eq!(column("price*(1 + tax)").name(), Some("price * (1 + tax)".into()));
+        assert_eq!(column("*").name(), None);
+        assert_eq!(column("t.*").name(), None);
     }
 }
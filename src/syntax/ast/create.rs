@@ -1 +1,204 @@
+use std::fmt;
+use std::mem;
+use error::Error;
+use super::*;
+use super::expr::Expr;
 
+/// `CREATE TABLE [IF NOT EXISTS] name (column_def, ...)`
+#[derive(Debug, Clone, PartialEq)]
+pub struct CreateTable<'a> {
+    pub name: Name<'a>,
+    pub if_not_exists: bool,
+    pub columns: Vec<ColumnDef<'a>>,
+    pub span: Span,
+}
+
+/// `name type [NOT NULL] [DEFAULT value] [SERIAL]`
+///
+/// Constraints may be given in any order following the type
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColumnDef<'a> {
+    pub name: Name<'a>,
+    pub ty: Token<'a>,
+    pub not_null: bool,
+    pub default: Option<Expr<'a>>,
+    pub serial: bool,
+    pub span: Span,
+}
+
+impl<'a> Syntax<'a> for ColumnDef<'a> {
+    type Output = Self;
+    fn parse(parser: &mut Parser<'a>) -> ParserResult<ColumnDef<'a>> {
+        let start = parser.peek_span();
+        let name = Identifier::parse(parser)?;
+        let ty = parser.expect_type()?;
+        let mut def = ColumnDef {
+            name,
+            ty,
+            not_null: false,
+            default: None,
+            serial: false,
+            span: start,
+        };
+
+        // Each constraint may be given at most once
+        loop {
+            let constraint = parser.peek_span();
+            let (name, repeated) = if parser.pop_if(&Token::NOT) {
+                parser.expect(&Token::NULL)?;
+                ("NOT NULL", mem::replace(&mut def.not_null, true))
+            } else if parser.pop_if(&Token::DEFAULT) {
+                ("DEFAULT", def.default.replace(Expr::parse(parser)?).is_some())
+            } else if parser.pop_if(&Token::SERIAL) {
+                ("SERIAL", mem::replace(&mut def.serial, true))
+            } else {
+                break;
+            };
+            if repeated {
+                return Err(Error::DuplicateConstraint {
+                    constraint: name.into(),
+                    span: parser.span_from(constraint),
+                });
+            }
+        }
+        def.span = parser.span_from(start);
+        Ok(def)
+    }
+}
+
+impl<'a> fmt::Display for ColumnDef<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {}", self.name, self.ty)?;
+        if self.not_null {
+            write!(f, " NOT NULL")?;
+        }
+        if let Some(ref default) = self.default {
+            write!(f, " DEFAULT {}", default)?;
+        }
+        if self.serial {
+            write!(f, " SERIAL")?;
+        }
+        Ok(())
+    }
+}
+
+impl<'a> fmt::Display for CreateTable<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "CREATE TABLE ")?;
+        if self.if_not_exists {
+            write!(f, "IF NOT EXISTS ")?;
+        }
+        write!(f, "{} (", self.name)?;
+        write_comma_delimited(f, &self.columns)?;
+        write!(f, ")")
+    }
+}
+
+impl<'a> Syntax<'a> for CreateTable<'a> {
+    type Output = Self;
+    fn parse(parser: &mut Parser<'a>) -> ParserResult<CreateTable<'a>> {
+        let start = parser.peek_span();
+        parser.expect(&Token::CREATE)?;
+        parser.expect(&Token::TABLE)?;
+        let if_not_exists = if parser.pop_if(&Token::IF) {
+            parser.expect(&Token::NOT)?;
+            parser.expect(&Token::EXISTS)?;
+            true
+        } else {
+            false
+        };
+        let name = Identifier::parse(parser)?;
+        parser.expect(&Token::LEFTPAREN)?;
+        let columns = ColumnDef::parse_comma_delimited(parser)?;
+        parser.expect(&Token::RIGHTPAREN)?;
+
+        Ok(CreateTable {
+            name,
+            if_not_exists,
+            columns,
+            span: parser.span_from(start),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::super::lexer::Lexer;
+    use super::super::expr::ExprKind;
+
+    #[test]
+    fn create_table() {
+        let s = "create table if not exists users (
+                id integer not null serial,
+                name text default `anonymous`,
+                score float,
+                avatar blob
+            );";
+        let mut parser = Lexer::lex(s).unwrap();
+        let create = CreateTable::parse(&mut parser).unwrap();
+
+        assert_eq!(create.name.token, Token::Identifier("users".into()));
+        assert!(create.if_not_exists);
+
+        let names: Vec<Token> = create.columns.iter().map(|c| c.name.token.clone()).collect();
+        let types: Vec<Token> = create.columns.iter().map(|c| c.ty.clone()).collect();
+        assert_eq!(
+            names,
+            vec![
+                Token::Identifier("id".into()),
+                Token::Identifier("name".into()),
+                Token::Identifier("score".into()),
+                Token::Identifier("avatar".into()),
+            ]
+        );
+        assert_eq!(
+            types,
+            vec![Token::INTEGER, Token::TEXT, Token::FLOAT, Token::BLOB]
+        );
+
+        let id = &create.columns[0];
+        assert!(id.not_null && id.serial && id.default.is_none());
+        assert_eq!(&s[id.span.start..id.span.end], "id integer not null serial");
+        assert_eq!(id.span.line, 2);
+
+        let name = &create.columns[1];
+        assert!(!name.not_null && !name.serial);
+        assert_eq!(
+            name.default.as_ref().map(|e| &e.kind),
+            Some(&ExprKind::Literal(Token::StringLiteral("anonymous".into())))
+        );
+
+        for column in &create.columns[2..] {
+            assert!(!column.not_null && !column.serial && column.default.is_none());
+        }
+        assert!(parser.pop_if(&Token::SEMICOLON));
+    }
+
+    #[test]
+    fn duplicate_constraint() {
+        for &(s, constraint, repeated) in &[
+            ("a int default 1 default 2", "DEFAULT", "default 2"),
+            ("a int not null serial not null", "NOT NULL", "not null"),
+            ("a int serial serial", "SERIAL", "serial"),
+        ] {
+            let mut parser = Lexer::lex(s).unwrap();
+            match ColumnDef::parse(&mut parser) {
+                Err(Error::DuplicateConstraint {
+                    constraint: ref c,
+                    span,
+                }) => {
+                    assert_eq!(c, constraint);
+                    assert_eq!(&s[span.start..span.end], repeated);
+                }
+                e => panic!("Expected duplicate {}, found {:?}", constraint, e),
+            }
+        }
+    }
+
+    #[test]
+    fn create_table_missing_not() {
+        let mut parser = Lexer::lex("create table if exists users (id integer)").unwrap();
+        assert!(CreateTable::parse(&mut parser).is_err());
+    }
+}
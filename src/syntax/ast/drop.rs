@@ -0,0 +1,75 @@
+use std::fmt;
+use super::*;
+
+/// `DROP TABLE [IF EXISTS] name, ...`
+#[derive(Debug, Clone, PartialEq)]
+pub struct DropTable<'a> {
+    pub names: Vec<Name<'a>>,
+    pub if_exists: bool,
+    pub span: Span,
+}
+
+impl<'a> fmt::Display for DropTable<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "DROP TABLE ")?;
+        if self.if_exists {
+            write!(f, "IF EXISTS ")?;
+        }
+        write_comma_delimited(f, &self.names)
+    }
+}
+
+impl<'a> Syntax<'a> for DropTable<'a> {
+    type Output = Self;
+    fn parse(parser: &mut Parser<'a>) -> ParserResult<DropTable<'a>> {
+        let start = parser.peek_span();
+        parser.expect(&Token::DROP)?;
+        parser.expect(&Token::TABLE)?;
+        let if_exists = if parser.pop_if(&Token::IF) {
+            parser.expect(&Token::EXISTS)?;
+            true
+        } else {
+            false
+        };
+        let names = Identifier::parse_comma_delimited(parser)?;
+        Ok(DropTable {
+            names,
+            if_exists,
+            span: parser.span_from(start),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::super::lexer::Lexer;
+
+    #[test]
+    fn drop_table() {
+        let mut parser = Lexer::lex("drop table if exists t1, t2;").unwrap();
+        assert_eq!(
+            DropTable::parse(&mut parser).unwrap(),
+            DropTable {
+                names: vec![
+                    Name {
+                        token: Token::Identifier("t1".into()),
+                        span: Span::new(21, 23, 1, 22),
+                    },
+                    Name {
+                        token: Token::Identifier("t2".into()),
+                        span: Span::new(25, 27, 1, 26),
+                    },
+                ],
+                if_exists: true,
+                span: Span::new(0, 27, 1, 1),
+            }
+        );
+        assert!(parser.pop_if(&Token::SEMICOLON));
+
+        let mut parser = Lexer::lex("drop table t1").unwrap();
+        let drop = DropTable::parse(&mut parser).unwrap();
+        assert!(!drop.if_exists);
+        assert_eq!(drop.names.len(), 1);
+    }
+}
@@ -0,0 +1,521 @@
+//! Expression grammar
+//!
+//! Expressions are parsed with a Pratt parser, using the binding power of
+//! each operator to resolve precedence and associativity. From loosest to
+//! tightest binding:
+//!
+//! | Operators                  | Associativity |
+//! |----------------------------|---------------|
+//! | `OR`                       | left          |
+//! | `AND`                      | left          |
+//! | `NOT`                      | prefix        |
+//! | `=` `<>`                   | left          |
+//! | `<` `<=` `>` `>=`          | left          |
+//! | `+` `-`                    | left          |
+//! | `*` `/`                    | left          |
+//! | `\|\|`                     | left          |
+//! | unary `-` `+`              | prefix        |
+use std::fmt;
+use error::Error;
+use super::*;
+use super::super::token::Placeholder;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UnaryOp {
+    Not,
+    Negate,
+    Plus,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BinaryOp {
+    Or,
+    And,
+    Equal,
+    NotEqual,
+    LessThan,
+    LessThanOrEqual,
+    GreaterThan,
+    GreaterThanOrEqual,
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Concat,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Expr<'a> {
+    pub kind: ExprKind<'a>,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExprKind<'a> {
+    /// String, number or `NULL` literal
+    Literal(Token<'a>),
+    /// Unqualified column reference
+    Column(Token<'a>),
+    /// `table.column` reference
+    QualifiedColumn(Token<'a>, Token<'a>),
+    Unary(UnaryOp, Box<Expr<'a>>),
+    Binary(Box<Expr<'a>>, BinaryOp, Box<Expr<'a>>),
+    /// Parenthesized expression
+    Nested(Box<Expr<'a>>),
+    /// Parameter of a prepared statement, replaced by a literal when bound
+    Placeholder(Placeholder<'a>),
+}
+
+/// Binding power of the `NOT` prefix operator
+const NOT_BP: u8 = 5;
+/// Binding power of the unary `-` and `+` prefix operators
+const UNARY_BP: u8 = 17;
+/// Integer literal that is only in range as the operand of `-`
+const MIN_MAGNITUDE: &str = "9223372036854775808";
+
+impl BinaryOp {
+    /// Match a token into a binary operator
+    pub fn from_token(tok: &Token) -> Option<BinaryOp> {
+        Some(match *tok {
+            Token::OR => BinaryOp::Or,
+            Token::AND => BinaryOp::And,
+            Token::EQUAL => BinaryOp::Equal,
+            Token::NOTEQUAL => BinaryOp::NotEqual,
+            Token::LESSTHAN => BinaryOp::LessThan,
+            Token::LESSTHANOREQUAL => BinaryOp::LessThanOrEqual,
+            Token::GREATERTHAN => BinaryOp::GreaterThan,
+            Token::GREATERTHANOREQUAL => BinaryOp::GreaterThanOrEqual,
+            Token::PLUS => BinaryOp::Add,
+            Token::MINUS => BinaryOp::Subtract,
+            Token::ASTERISK => BinaryOp::Multiply,
+            Token::FORWARDSLASH => BinaryOp::Divide,
+            Token::DOUBLEPIPE => BinaryOp::Concat,
+            _ => return None,
+        })
+    }
+
+    /// Left and right binding power. All binary operators are left
+    /// associative, so the right side always binds slightly tighter
+    fn binding_power(self) -> (u8, u8) {
+        let bp = match self {
+            BinaryOp::Or => 1,
+            BinaryOp::And => 3,
+            BinaryOp::Equal | BinaryOp::NotEqual => 7,
+            BinaryOp::LessThan
+            | BinaryOp::LessThanOrEqual
+            | BinaryOp::GreaterThan
+            | BinaryOp::GreaterThanOrEqual => 9,
+            BinaryOp::Add | BinaryOp::Subtract => 11,
+            BinaryOp::Multiply | BinaryOp::Divide => 13,
+            BinaryOp::Concat => 15,
+        };
+        (bp, bp + 1)
+    }
+}
+
+impl<'a> Expr<'a> {
+    /// Parse a prefix operator, literal, column reference or nested
+    /// expression
+    fn parse_prefix(parser: &mut Parser<'a>) -> ParserResult<Expr<'a>> {
+        let start = parser.peek_span();
+        let tok = match parser.peek() {
+            Some(&Token::NOT)
+            | Some(&Token::MINUS)
+            | Some(&Token::PLUS)
+            | Some(&Token::LEFTPAREN)
+            | Some(&Token::Identifier(_))
+            | Some(&Token::QuotedIdentifier(_))
+            | Some(&Token::StringLiteral(_))
+            | Some(&Token::IntegerLiteral(_))
+            | Some(&Token::FloatLiteral(_))
+            | Some(&Token::Placeholder(_))
+            | Some(&Token::NULL) => parser.pop()?,
+            _ => return Err(parser.unexpected(&["expression"])),
+        };
+        let kind = match tok {
+            Token::NOT => ExprKind::Unary(UnaryOp::Not, Box::new(Expr::parse_bp(parser, NOT_BP)?)),
+            // The magnitude of `i64::MIN` is only valid when negated
+            Token::MINUS if parser.peek_is(&Token::IntegerLiteral(i64::MIN)) => {
+                ExprKind::Literal(parser.pop()?)
+            }
+            Token::MINUS => ExprKind::Unary(
+                UnaryOp::Negate,
+                Box::new(Expr::parse_bp(parser, UNARY_BP)?),
+            ),
+            Token::PLUS => ExprKind::Unary(
+                UnaryOp::Plus,
+                Box::new(Expr::parse_bp(parser, UNARY_BP)?),
+            ),
+            Token::LEFTPAREN => {
+                let expr = Expr::parse_bp(parser, 0)?;
+                parser.expect(&Token::RIGHTPAREN)?;
+                ExprKind::Nested(Box::new(expr))
+            }
+            Token::Identifier(_) | Token::QuotedIdentifier(_) => {
+                if parser.pop_if(&Token::DOT) {
+                    let column = parser.expect_identifier()?;
+                    ExprKind::QualifiedColumn(tok, column)
+                } else {
+                    ExprKind::Column(tok)
+                }
+            }
+            Token::Placeholder(p) => ExprKind::Placeholder(p),
+            Token::IntegerLiteral(i64::MIN) => {
+                return Err(Error::NumberOverflow {
+                    literal: MIN_MAGNITUDE.into(),
+                    span: start,
+                })
+            }
+            // String, number or NULL literal
+            _ => ExprKind::Literal(tok),
+        };
+        Ok(Expr {
+            kind,
+            span: parser.span_from(start),
+        })
+    }
+
+    /// Parse an expression, consuming binary operators for as long as they
+    /// bind at least as tightly as `min_bp`
+    fn parse_bp(parser: &mut Parser<'a>, min_bp: u8) -> ParserResult<Expr<'a>> {
+        let lhs = Expr::parse_prefix(parser)?;
+        Expr::parse_infix(parser, lhs, min_bp)
+    }
+
+    /// Continue parsing an expression whose leftmost operand, `lhs`, has
+    /// already been parsed
+    pub(super) fn parse_infix(
+        parser: &mut Parser<'a>,
+        mut lhs: Expr<'a>,
+        min_bp: u8,
+    ) -> ParserResult<Expr<'a>> {
+        while let Some(op) = parser.peek().and_then(BinaryOp::from_token) {
+            let (l_bp, r_bp) = op.binding_power();
+            if l_bp < min_bp {
+                break;
+            }
+            parser.pop()?;
+            let rhs = Expr::parse_bp(parser, r_bp)?;
+            let span = lhs.span.extend_to(rhs.span.end);
+            lhs = Expr {
+                kind: ExprKind::Binary(Box::new(lhs), op, Box::new(rhs)),
+                span,
+            };
+        }
+        Ok(lhs)
+    }
+}
+
+impl fmt::Display for UnaryOp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = match *self {
+            UnaryOp::Not => "NOT",
+            UnaryOp::Negate => "-",
+            UnaryOp::Plus => "+",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+impl fmt::Display for BinaryOp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = match *self {
+            BinaryOp::Or => "OR",
+            BinaryOp::And => "AND",
+            BinaryOp::Equal => "=",
+            BinaryOp::NotEqual => "<>",
+            BinaryOp::LessThan => "<",
+            BinaryOp::LessThanOrEqual => "<=",
+            BinaryOp::GreaterThan => ">",
+            BinaryOp::GreaterThanOrEqual => ">=",
+            BinaryOp::Add => "+",
+            BinaryOp::Subtract => "-",
+            BinaryOp::Multiply => "*",
+            BinaryOp::Divide => "/",
+            BinaryOp::Concat => "||",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+/// Operand of an operator with binding power `.1`. A binary expression
+/// that binds looser than its operator is parenthesized, so that the tree
+/// is read back with the same shape. So is a `NOT` under any tighter
+/// operator, as it would otherwise take in whatever follows it
+struct Operand<'e, 'a: 'e>(&'e Expr<'a>, u8);
+
+impl<'e, 'a> fmt::Display for Operand<'e, 'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.0.kind {
+            ExprKind::Binary(_, op, _) if op.binding_power().0 < self.1 => write!(f, "({})", self.0),
+            ExprKind::Unary(UnaryOp::Not, _) if NOT_BP < self.1 => write!(f, "({})", self.0),
+            _ => write!(f, "{}", self.0),
+        }
+    }
+}
+
+impl<'a> fmt::Display for Expr<'a> {
+    /// Write the expression as SQL
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.kind {
+            ExprKind::Literal(ref tok) | ExprKind::Column(ref tok) => write!(f, "{}", tok),
+            ExprKind::QualifiedColumn(ref table, ref column) => write!(f, "{}.{}", table, column),
+            ExprKind::Placeholder(ref p) => write!(f, "{}", p),
+            ExprKind::Nested(ref e) => write!(f, "({})", e),
+            ExprKind::Unary(UnaryOp::Not, ref e) => write!(f, "NOT {}", Operand(e, NOT_BP)),
+            ExprKind::Unary(op, ref e) => {
+                let operand = Operand(e, UNARY_BP).to_string();
+                // `--` would begin a comment
+                if operand.starts_with('-') {
+                    write!(f, "{} {}", op, operand)
+                } else {
+                    write!(f, "{}{}", op, operand)
+                }
+            }
+            ExprKind::Binary(ref l, op, ref r) => {
+                let (l_bp, r_bp) = op.binding_power();
+                write!(f, "{} {} {}", Operand(l, l_bp), op, Operand(r, r_bp))
+            }
+        }
+    }
+}
+
+impl<'a> Syntax<'a> for Expr<'a> {
+    type Output = Expr<'a>;
+    fn parse(parser: &mut Parser<'a>) -> ParserResult<Expr<'a>> {
+        Expr::parse_bp(parser, 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::super::lexer::Lexer;
+
+    /// Reset all spans, so that trees can be compared by structure alone
+    fn strip(expr: Expr) -> Expr {
+        let kind = match expr.kind {
+            ExprKind::Unary(op, e) => ExprKind::Unary(op, Box::new(strip(*e))),
+            ExprKind::Binary(l, op, r) => {
+                ExprKind::Binary(Box::new(strip(*l)), op, Box::new(strip(*r)))
+            }
+            ExprKind::Nested(e) => ExprKind::Nested(Box::new(strip(*e))),
+            kind => kind,
+        };
+        Expr {
+            kind,
+            span: Span::default(),
+        }
+    }
+
+    /// Remove parentheses, leaving the shape of the tree that they imply
+    fn unnest(expr: Expr) -> Expr {
+        let kind = match expr.kind {
+            ExprKind::Unary(op, e) => ExprKind::Unary(op, Box::new(unnest(*e))),
+            ExprKind::Binary(l, op, r) => {
+                ExprKind::Binary(Box::new(unnest(*l)), op, Box::new(unnest(*r)))
+            }
+            ExprKind::Nested(e) => return unnest(*e),
+            kind => kind,
+        };
+        Expr { kind, ..expr }
+    }
+
+    fn parse<'a>(s: &'a str) -> Expr<'a> {
+        let mut parser = Lexer::lex(s).unwrap();
+        let expr = Expr::parse(&mut parser).unwrap();
+        assert_eq!(parser.peek(), None);
+        strip(expr)
+    }
+
+    fn node(kind: ExprKind) -> Box<Expr> {
+        Box::new(Expr {
+            kind,
+            span: Span::default(),
+        })
+    }
+
+    fn column<'a>(s: &'a str) -> Box<Expr<'a>> {
+        node(ExprKind::Column(Token::Identifier(s.into())))
+    }
+
+    fn number(i: i64) -> Box<Expr<'static>> {
+        node(ExprKind::Literal(Token::IntegerLiteral(i)))
+    }
+
+    fn binary<'a>(l: Box<Expr<'a>>, op: BinaryOp, r: Box<Expr<'a>>) -> Box<Expr<'a>> {
+        node(ExprKind::Binary(l, op, r))
+    }
+
+    fn unary(op: UnaryOp, e: Box<Expr>) -> Box<Expr> {
+        node(ExprKind::Unary(op, e))
+    }
+
+    #[test]
+    fn precedence() {
+        // a + (b * 2)
+        assert_eq!(
+            parse("a + b * 2"),
+            *binary(
+                column("a"),
+                BinaryOp::Add,
+                binary(column("b"), BinaryOp::Multiply, number(2)),
+            )
+        );
+        // (a = 1) OR ((b > 2) AND (NOT c))
+        assert_eq!(
+            parse("a = 1 or b > 2 and not c"),
+            *binary(
+                binary(column("a"), BinaryOp::Equal, number(1)),
+                BinaryOp::Or,
+                binary(
+                    binary(column("b"), BinaryOp::GreaterThan, number(2)),
+                    BinaryOp::And,
+                    unary(UnaryOp::Not, column("c")),
+                ),
+            )
+        );
+    }
+
+    #[test]
+    fn associativity() {
+        // (a - b) - c
+        assert_eq!(
+            parse("a - b - c"),
+            *binary(
+                binary(column("a"), BinaryOp::Subtract, column("b")),
+                BinaryOp::Subtract,
+                column("c"),
+            )
+        );
+    }
+
+    #[test]
+    fn unary_and_nested() {
+        // (-a) * (b + c)
+        assert_eq!(
+            parse("- a * (b + c)"),
+            *binary(
+                unary(UnaryOp::Negate, column("a")),
+                BinaryOp::Multiply,
+                node(ExprKind::Nested(
+                    binary(column("b"), BinaryOp::Add, column("c")),
+                )),
+            )
+        );
+        // NOT binds looser than comparison: NOT (a = b)
+        assert_eq!(
+            parse("not a = b"),
+            *unary(
+                UnaryOp::Not,
+                binary(column("a"), BinaryOp::Equal, column("b")),
+            )
+        );
+    }
+
+    #[test]
+    fn qualified_column() {
+        assert_eq!(
+            parse("users.id || `x`"),
+            *binary(
+                node(ExprKind::QualifiedColumn(
+                    Token::Identifier("users".into()),
+                    Token::Identifier("id".into()),
+                )),
+                BinaryOp::Concat,
+                node(ExprKind::Literal(Token::StringLiteral("x".into()))),
+            )
+        );
+    }
+
+    #[test]
+    fn spans() {
+        let s = "a * (b + 10)";
+        let mut parser = Lexer::lex(s).unwrap();
+        let expr = Expr::parse(&mut parser).unwrap();
+        assert_eq!(expr.span, Span::new(0, 12, 1, 1));
+        match expr.kind {
+            ExprKind::Binary(l, _, r) => {
+                assert_eq!(&s[l.span.start..l.span.end], "a");
+                assert_eq!(&s[r.span.start..r.span.end], "(b + 10)");
+                assert_eq!(r.span.column, 5);
+            }
+            _ => panic!("Expected binary expression"),
+        }
+    }
+
+    #[test]
+    fn min_integer() {
+        assert_eq!(parse("-9223372036854775808"), *number(i64::MIN));
+        assert_eq!(
+            parse("- -9223372036854775808 * 2"),
+            *binary(
+                unary(UnaryOp::Negate, number(i64::MIN)),
+                BinaryOp::Multiply,
+                number(2),
+            )
+        );
+        assert_eq!(number(i64::MIN).to_string(), "-9223372036854775808");
+        for &(s, column) in &[("9223372036854775808", 1), ("1 - 9223372036854775808", 5)] {
+            let mut parser = Lexer::lex(s).unwrap();
+            match Expr::parse(&mut parser) {
+                Err(Error::NumberOverflow { literal, span }) => {
+                    assert_eq!(literal, "9223372036854775808");
+                    assert_eq!(span.column, column);
+                }
+                e => panic!("Expected overflow in {}, found {:?}", s, e),
+            }
+        }
+    }
+
+    #[test]
+    fn missing_operand() {
+        let mut parser = Lexer::lex("a + ").unwrap();
+        assert!(Expr::parse(&mut parser).is_err());
+        let mut parser = Lexer::lex("(a + b").unwrap();
+        assert!(Expr::parse(&mut parser).is_err());
+    }
+
+    #[test]
+    fn display() {
+        for s in &[
+            "a + b * 2",
+            "(a + b) * 2",
+            "a - (b - c)",
+            "NOT a = 1 OR b.c <> 'it''s' AND -x || \"Y\"",
+            "- -1.5 >= +$1",
+            "NOT (a OR :b)",
+        ] {
+            assert_eq!(&parse(s).to_string(), s);
+        }
+
+        // Parentheses are added where precedence requires them
+        let expr = binary(
+            binary(column("a"), BinaryOp::Add, column("b")),
+            BinaryOp::Multiply,
+            binary(column("c"), BinaryOp::Multiply, column("d")),
+        );
+        assert_eq!(expr.to_string(), "(a + b) * (c * d)");
+
+        // As is a `NOT` under an operator that binds more tightly
+        let not = |e| unary(UnaryOp::Not, e);
+        for &(ref expr, s) in &[
+            (binary(not(column("a")), BinaryOp::Equal, column("b")), "(NOT a) = b"),
+            (
+                binary(
+                    binary(column("a"), BinaryOp::Equal, not(column("b"))),
+                    BinaryOp::Equal,
+                    column("c"),
+                ),
+                "a = (NOT b) = c",
+            ),
+            (unary(UnaryOp::Negate, not(column("a"))), "-(NOT a)"),
+            (binary(not(column("a")), BinaryOp::And, not(column("b"))), "NOT a AND NOT b"),
+            (not(not(column("a"))), "NOT NOT a"),
+        ] {
+            assert_eq!(expr.to_string(), s);
+            assert_eq!(unnest(parse(s)), **expr);
+        }
+        assert_eq!(unary(UnaryOp::Negate, number(-1)).to_string(), "- -1");
+    }
+}
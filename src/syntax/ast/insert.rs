@@ -0,0 +1,128 @@
+use std::fmt;
+use super::*;
+use super::expr::Expr;
+
+/// `INSERT INTO table [(column, ...)] VALUES (expr, ...), ...`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Insert<'a> {
+    pub table: Name<'a>,
+    /// Explicit column list, empty if omitted
+    pub columns: Vec<Name<'a>>,
+    pub rows: Vec<Vec<Expr<'a>>>,
+    pub span: Span,
+}
+
+/// A single parenthesized tuple of values
+struct Row;
+
+impl<'a> Syntax<'a> for Row {
+    type Output = Vec<Expr<'a>>;
+    fn parse(parser: &mut Parser<'a>) -> ParserResult<Vec<Expr<'a>>> {
+        parser.expect(&Token::LEFTPAREN)?;
+        // Close the tuple even if a value was bad, so that the rest of the
+        // rows can still be checked
+        let values = Expr::parse_comma_delimited(parser);
+        parser.expect(&Token::RIGHTPAREN)?;
+        values
+    }
+}
+
+impl<'a> fmt::Display for Insert<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "INSERT INTO {} ", self.table)?;
+        if !self.columns.is_empty() {
+            write!(f, "(")?;
+            write_comma_delimited(f, &self.columns)?;
+            write!(f, ") ")?;
+        }
+        write!(f, "VALUES ")?;
+        for (i, row) in self.rows.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "(")?;
+            write_comma_delimited(f, row)?;
+            write!(f, ")")?;
+        }
+        Ok(())
+    }
+}
+
+impl<'a> Syntax<'a> for Insert<'a> {
+    type Output = Self;
+    fn parse(parser: &mut Parser<'a>) -> ParserResult<Insert<'a>> {
+        let start = parser.peek_span();
+        parser.expect(&Token::INSERT)?;
+        parser.expect(&Token::INTO)?;
+        let table = Identifier::parse(parser)?;
+
+        let columns = if parser.pop_if(&Token::LEFTPAREN) {
+            let columns = Identifier::parse_comma_delimited(parser)?;
+            parser.expect(&Token::RIGHTPAREN)?;
+            columns
+        } else {
+            Vec::new()
+        };
+
+        parser.expect(&Token::VALUES)?;
+        let rows = Row::parse_comma_delimited(parser)?;
+        Ok(Insert {
+            table,
+            columns,
+            rows,
+            span: parser.span_from(start),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::super::lexer::Lexer;
+    use super::super::expr::ExprKind;
+
+    #[test]
+    fn insert() {
+        let mut parser = Lexer::lex(
+            "insert into users (id, name) values (1, `alice`), (2, `bob`);",
+        ).unwrap();
+        let insert = Insert::parse(&mut parser).unwrap();
+
+        assert_eq!(insert.table.token, Token::Identifier("users".into()));
+        assert_eq!(
+            insert.columns.iter().map(|c| c.token.clone()).collect::<Vec<_>>(),
+            vec![
+                Token::Identifier("id".into()),
+                Token::Identifier("name".into()),
+            ]
+        );
+        let rows: Vec<Vec<ExprKind>> = insert
+            .rows
+            .into_iter()
+            .map(|row| row.into_iter().map(|e| e.kind).collect())
+            .collect();
+        assert_eq!(
+            rows,
+            vec![
+                vec![
+                    ExprKind::Literal(Token::IntegerLiteral(1)),
+                    ExprKind::Literal(Token::StringLiteral("alice".into())),
+                ],
+                vec![
+                    ExprKind::Literal(Token::IntegerLiteral(2)),
+                    ExprKind::Literal(Token::StringLiteral("bob".into())),
+                ],
+            ]
+        );
+        assert!(parser.pop_if(&Token::SEMICOLON));
+    }
+
+    #[test]
+    fn insert_without_columns() {
+        let mut parser = Lexer::lex("insert into users values (1 + 2, null)").unwrap();
+        let insert = Insert::parse(&mut parser).unwrap();
+        assert!(insert.columns.is_empty());
+        assert_eq!(insert.rows.len(), 1);
+        assert_eq!(insert.rows[0].len(), 2);
+    }
+}
@@ -1,39 +1,112 @@
-use super::parser::{Parser, ParserError, ParserResult};
-use super::token::Token;
+use std::fmt;
+use super::parser::{Parser, ParserResult};
+use super::token::{Span, Token};
 
 pub mod select;
+pub mod bind;
 pub mod create;
+pub mod drop;
 pub mod columns;
+pub mod expr;
+pub mod insert;
+pub mod statement;
+pub mod visit;
 
-pub trait Syntax: Sized {
+/// Syntax that can be parsed from a stream of tokens. `'a` is the lifetime
+/// of the source text that tokens may borrow from
+pub trait Syntax<'a>: Sized {
     type Output;
-    fn parse(parser: &mut Parser) -> ParserResult<Self::Output>;
+    fn parse(parser: &mut Parser<'a>) -> ParserResult<Self::Output>;
 }
 
-struct CommaDelimited<R: Syntax>(R);
-impl<R> SyntaxExt for R
+/// A bare identifier, such as a table or column name
+pub struct Identifier;
+
+/// An identifier naming a table or column, and where it was found
+#[derive(Debug, Clone, PartialEq)]
+pub struct Name<'a> {
+    pub token: Token<'a>,
+    pub span: Span,
+}
+
+impl<'a> Syntax<'a> for Identifier {
+    type Output = Name<'a>;
+    fn parse(parser: &mut Parser<'a>) -> ParserResult<Name<'a>> {
+        let span = parser.peek_span();
+        let token = parser.expect_identifier()?;
+        Ok(Name { token, span })
+    }
+}
+
+impl<'a> fmt::Display for Name<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.token)
+    }
+}
+
+struct CommaDelimited<R>(R);
+
+/// Write `items` separated by commas
+fn write_comma_delimited<T: fmt::Display>(f: &mut fmt::Formatter, items: &[T]) -> fmt::Result {
+    for (i, item) in items.iter().enumerate() {
+        if i > 0 {
+            write!(f, ", ")?;
+        }
+        write!(f, "{}", item)?;
+    }
+    Ok(())
+}
+
+/// Tokens that may follow an element of a comma delimited list, and where
+/// parsing can resume after a bad element
+const LIST_RECOVERY: &[Token] = &[
+    Token::COMMA,
+    Token::RIGHTPAREN,
+    Token::FROM,
+    Token::WHERE,
+    Token::ORDER,
+    Token::VALUES,
+];
+impl<'a, R> SyntaxExt<'a> for R
 where
-    R: Syntax,
+    R: Syntax<'a>,
 {
 }
 
-pub trait SyntaxExt: Syntax {
-    fn parse_comma_delimited(parser: &mut Parser) -> ParserResult<Vec<Self::Output>> {
+pub trait SyntaxExt<'a>: Syntax<'a> {
+    fn parse_comma_delimited(parser: &mut Parser<'a>) -> ParserResult<Vec<Self::Output>> {
         CommaDelimited::<Self>::parse(parser)
     }
 }
 
-impl<R: Syntax> Syntax for CommaDelimited<R> {
+impl<'a, R: Syntax<'a>> Syntax<'a> for CommaDelimited<R> {
     type Output = Vec<R::Output>;
 
-    fn parse(parser: &mut Parser) -> ParserResult<Self::Output> {
+    /// If an element fails to parse, skip ahead to the next comma and keep
+    /// going, so that every bad element in the list is diagnosed. The first
+    /// error is returned, and any others are reported to the parser
+    fn parse(parser: &mut Parser<'a>) -> ParserResult<Self::Output> {
         let mut v: Vec<R::Output> = Vec::new();
-        v.push(R::parse(parser).unwrap());
-        while parser.pop_if(&Token::COMMA) {
-            let value = R::parse(parser).unwrap();
-            v.push(value);
+        let mut error = None;
+        loop {
+            match R::parse(parser) {
+                Ok(value) => v.push(value),
+                Err(e) => {
+                    match error {
+                        None => error = Some(e),
+                        Some(_) => parser.report(e),
+                    }
+                    parser.synchronize(LIST_RECOVERY);
+                }
+            }
+            if !parser.pop_if(&Token::COMMA) {
+                break;
+            }
+        }
+        match error {
+            Some(e) => Err(e),
+            None => Ok(v),
         }
-        Ok(v)
     }
 }
 
@@ -41,6 +114,7 @@ impl<R: Syntax> Syntax for CommaDelimited<R> {
 mod tests {
     use super::*;
     use super::super::lexer::Lexer;
+    use error::Error;
 
     #[test]
     fn comma_delimited() {
@@ -56,11 +130,28 @@ mod tests {
         ];
 
         assert_eq!(correct.len(), v.len());
-        for (tok, _v) in correct.into_iter().zip(v.into_iter()) {
-            match _v {
-                columns::Column::Expr(t) => assert_eq!(t, tok),
-                _ => panic!("Mismatch!"),
+        for (tok, _v) in correct.into_iter().zip(v) {
+            match _v {
+                columns::Column::Expr(e, None) => assert_eq!(e.kind, expr::ExprKind::Column(tok)),
+                _ => panic!("Mismatch!"),
             };
         }
     }
+
+    #[test]
+    fn comma_delimited_errors() {
+        let mut parser = Lexer::lex("a +, b, c * / d, e from t").unwrap();
+        match columns::Column::parse_comma_delimited(&mut parser) {
+            Err(Error::UnexpectedToken { found, .. }) => assert_eq!(found, Token::COMMA),
+            _ => panic!("Expected error"),
+        }
+        let errors = parser.take_errors();
+        assert_eq!(errors.len(), 1);
+        match errors[0] {
+            Error::UnexpectedToken { ref found, .. } => assert_eq!(found, &Token::FORWARDSLASH),
+            _ => panic!("Expected error"),
+        }
+        // Parsing can pick up again after the list
+        assert!(parser.peek_is(&Token::FROM));
+    }
 }
@@ -1,11 +1,184 @@
+use std::fmt;
 use super::*;
+use super::columns::Column;
+use super::expr::Expr;
 
-struct Select(u8);
+/// `SELECT columns FROM table [WHERE predicate] [ORDER BY ordering, ...]`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Select<'a> {
+    pub columns: Vec<Column<'a>>,
+    pub table: Name<'a>,
+    pub predicate: Option<Expr<'a>>,
+    pub order_by: Vec<OrderBy<'a>>,
+    pub span: Span,
+}
+
+/// A single `ORDER BY` term, ascending unless `DESC` is given
+#[derive(Debug, Clone, PartialEq)]
+pub struct OrderBy<'a> {
+    pub expr: Expr<'a>,
+    pub descending: bool,
+    pub span: Span,
+}
 
-impl Syntax for Select {
+impl<'a> Syntax<'a> for OrderBy<'a> {
     type Output = Self;
-    fn parse(parser: &mut Parser) -> ParserResult<Select> {
+    fn parse(parser: &mut Parser<'a>) -> ParserResult<OrderBy<'a>> {
+        let start = parser.peek_span();
+        let expr = Expr::parse(parser)?;
+        let descending = if parser.pop_if(&Token::DESC) {
+            true
+        } else {
+            parser.pop_if(&Token::ASC);
+            false
+        };
+        Ok(OrderBy {
+            expr,
+            descending,
+            span: parser.span_from(start),
+        })
+    }
+}
+
+impl<'a> fmt::Display for OrderBy<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.expr)?;
+        if self.descending {
+            write!(f, " DESC")?;
+        }
+        Ok(())
+    }
+}
+
+impl<'a> fmt::Display for Select<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "SELECT ")?;
+        write_comma_delimited(f, &self.columns)?;
+        write!(f, " FROM {}", self.table)?;
+        if let Some(ref predicate) = self.predicate {
+            write!(f, " WHERE {}", predicate)?;
+        }
+        if !self.order_by.is_empty() {
+            write!(f, " ORDER BY ")?;
+            write_comma_delimited(f, &self.order_by)?;
+        }
+        Ok(())
+    }
+}
+
+impl<'a> Syntax<'a> for Select<'a> {
+    type Output = Self;
+    fn parse(parser: &mut Parser<'a>) -> ParserResult<Select<'a>> {
+        let start = parser.peek_span();
         parser.expect(&Token::SELECT)?;
-        Ok(Select(10))
+        let columns = Column::parse_comma_delimited(parser)?;
+        parser.expect(&Token::FROM)?;
+        let table = Identifier::parse(parser)?;
+
+        let predicate = if parser.pop_if(&Token::WHERE) {
+            Some(Expr::parse(parser)?)
+        } else {
+            None
+        };
+
+        let order_by = if parser.pop_if(&Token::ORDER) {
+            parser.expect(&Token::BY)?;
+            OrderBy::parse_comma_delimited(parser)?
+        } else {
+            Vec::new()
+        };
+
+        Ok(Select {
+            columns,
+            table,
+            predicate,
+            order_by,
+            span: parser.span_from(start),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::super::lexer::Lexer;
+    use super::super::expr::{BinaryOp, ExprKind};
+
+    #[test]
+    fn select() {
+        let s = "select row_1, row_2 from my_table where row_id > 0 order by row_1, row_2 desc;";
+        let mut parser = Lexer::lex(s).unwrap();
+        let select = Select::parse(&mut parser).unwrap();
+
+        assert_eq!(select.columns.len(), 2);
+        assert_eq!(select.table.token, Token::Identifier("my_table".into()));
+        assert_eq!(select.table.span, Span::new(25, 33, 1, 26));
+
+        let predicate = select.predicate.unwrap();
+        assert_eq!(&s[predicate.span.start..predicate.span.end], "row_id > 0");
+        match predicate.kind {
+            ExprKind::Binary(l, BinaryOp::GreaterThan, r) => {
+                assert_eq!(l.kind, ExprKind::Column(Token::Identifier("row_id".into())));
+                assert_eq!(r.kind, ExprKind::Literal(Token::IntegerLiteral(0)));
+            }
+            _ => panic!("Expected `row_id > 0`"),
+        }
+
+        assert_eq!(select.order_by.len(), 2);
+        assert_eq!(
+            select.order_by[0].expr.kind,
+            ExprKind::Column(Token::Identifier("row_1".into()))
+        );
+        assert!(!select.order_by[0].descending);
+        assert_eq!(&s[select.order_by[1].span.start..select.order_by[1].span.end], "row_2 desc");
+        assert!(select.order_by[1].descending);
+
+        // The statement span covers everything up to the semicolon
+        assert_eq!(select.span.start, 0);
+        assert_eq!(select.span.end, s.len() - 1);
+        assert!(parser.pop_if(&Token::SEMICOLON));
+    }
+
+    #[test]
+    fn select_quoted() {
+        // Quoted identifiers keep their case, and may be reserved words
+        let mut parser = Lexer::lex("select \"Order\".\"UserId\" from \"order\"").unwrap();
+        let select = Select::parse(&mut parser).unwrap();
+        assert_eq!(select.table.token, Token::QuotedIdentifier("order".into()));
+        match select.columns[0] {
+            Column::Expr(ref e, _) => assert_eq!(
+                e.kind,
+                ExprKind::QualifiedColumn(
+                    Token::QuotedIdentifier("Order".into()),
+                    Token::QuotedIdentifier("UserId".into()),
+                )
+            ),
+            _ => panic!("Expected column"),
+        }
+    }
+
+    #[test]
+    fn select_all() {
+        let mut parser = Lexer::lex("select * from my_table").unwrap();
+        let select = Select::parse(&mut parser).unwrap();
+        match select.columns[0] {
+            Column::All(span) => assert_eq!(span, Span::new(7, 8, 1, 8)),
+            _ => panic!("Expected Column::All"),
+        }
+        assert_eq!(select.predicate, None);
+        assert!(select.order_by.is_empty());
+    }
+
+    #[test]
+    fn select_aliases() {
+        let mut parser = Lexer::lex("select t.id as user_id, u.*, count c from t").unwrap();
+        let select = Select::parse(&mut parser).unwrap();
+        let names: Vec<_> = select.columns.iter().map(Column::name).collect();
+        assert_eq!(names, vec![Some("user_id".into()), None, Some("c".into())]);
+        assert_eq!(select.table.token, Token::Identifier("t".into()));
+        assert_eq!(
+            select.to_string(),
+            "SELECT t.id AS user_id, u.*, count AS c FROM t"
+        );
     }
 }
@@ -0,0 +1,80 @@
+use std::fmt;
+use super::*;
+use super::create::CreateTable;
+use super::drop::DropTable;
+use super::insert::Insert;
+use super::select::Select;
+
+/// Any top-level SQL statement
+#[derive(Debug, Clone, PartialEq)]
+pub enum Statement<'a> {
+    Select(Select<'a>),
+    Insert(Insert<'a>),
+    CreateTable(CreateTable<'a>),
+    DropTable(DropTable<'a>),
+}
+
+/// Keywords that may begin a statement
+const STATEMENTS: &[&str] = &["`SELECT`", "`INSERT`", "`CREATE`", "`DROP`"];
+
+impl<'a> Statement<'a> {
+    pub fn span(&self) -> Span {
+        match *self {
+            Statement::Select(ref s) => s.span,
+            Statement::Insert(ref s) => s.span,
+            Statement::CreateTable(ref s) => s.span,
+            Statement::DropTable(ref s) => s.span,
+        }
+    }
+}
+
+impl<'a> fmt::Display for Statement<'a> {
+    /// Write the statement as canonical SQL, on a single line and without
+    /// a terminating semicolon
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Statement::Select(ref s) => write!(f, "{}", s),
+            Statement::Insert(ref s) => write!(f, "{}", s),
+            Statement::CreateTable(ref s) => write!(f, "{}", s),
+            Statement::DropTable(ref s) => write!(f, "{}", s),
+        }
+    }
+}
+
+impl<'a> Syntax<'a> for Statement<'a> {
+    type Output = Self;
+    /// Dispatch to the statement parser matching the leading keyword
+    fn parse(parser: &mut Parser<'a>) -> ParserResult<Statement<'a>> {
+        match parser.peek() {
+            Some(&Token::SELECT) => Ok(Statement::Select(Select::parse(parser)?)),
+            Some(&Token::INSERT) => Ok(Statement::Insert(Insert::parse(parser)?)),
+            Some(&Token::CREATE) => Ok(Statement::CreateTable(CreateTable::parse(parser)?)),
+            Some(&Token::DROP) => Ok(Statement::DropTable(DropTable::parse(parser)?)),
+            _ => Err(parser.unexpected(STATEMENTS)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::super::lexer::Lexer;
+
+    #[test]
+    fn dispatch() {
+        let mut parser = Lexer::lex("drop table t1").unwrap();
+        match Statement::parse(&mut parser).unwrap() {
+            Statement::DropTable(_) => (),
+            s => panic!("Expected DROP TABLE, found {:?}", s),
+        }
+
+        let mut parser = Lexer::lex("create table t1 (id integer)").unwrap();
+        match Statement::parse(&mut parser).unwrap() {
+            Statement::CreateTable(_) => (),
+            s => panic!("Expected CREATE TABLE, found {:?}", s),
+        }
+
+        let mut parser = Lexer::lex("from t1").unwrap();
+        assert!(Statement::parse(&mut parser).is_err());
+    }
+}
@@ -0,0 +1,352 @@
+//! Traversal of the syntax tree
+//!
+//! `Visitor` walks a tree by reference, and `VisitorMut` walks it mutably so
+//! that nodes can be rewritten in place. Every method has a default
+//! implementation that calls the matching `walk_*` function to visit the
+//! children of the node, so an implementation only needs to override the
+//! methods for the nodes it is interested in. An overriding method can call
+//! the `walk_*` function itself to continue into the children.
+//!
+//! Children are visited in the order they appear in the source
+use super::*;
+use super::columns::Column;
+use super::create::{ColumnDef, CreateTable};
+use super::drop::DropTable;
+use super::expr::{Expr, ExprKind};
+use super::insert::Insert;
+use super::select::{OrderBy, Select};
+use super::statement::Statement;
+
+pub trait Visitor<'a> {
+    fn visit_statement(&mut self, statement: &Statement<'a>) {
+        walk_statement(self, statement)
+    }
+
+    fn visit_select(&mut self, select: &Select<'a>) {
+        walk_select(self, select)
+    }
+
+    fn visit_insert(&mut self, insert: &Insert<'a>) {
+        walk_insert(self, insert)
+    }
+
+    fn visit_create_table(&mut self, create: &CreateTable<'a>) {
+        walk_create_table(self, create)
+    }
+
+    fn visit_drop_table(&mut self, drop: &DropTable<'a>) {
+        walk_drop_table(self, drop)
+    }
+
+    /// A column in the projection of a `SELECT`
+    fn visit_column(&mut self, column: &Column<'a>) {
+        walk_column(self, column)
+    }
+
+    fn visit_order_by(&mut self, order: &OrderBy<'a>) {
+        walk_order_by(self, order)
+    }
+
+    fn visit_column_def(&mut self, def: &ColumnDef<'a>) {
+        walk_column_def(self, def)
+    }
+
+    fn visit_expr(&mut self, expr: &Expr<'a>) {
+        walk_expr(self, expr)
+    }
+
+    /// The name of a table, or of the table qualifying a column reference
+    fn visit_table_name(&mut self, _name: &Token<'a>) {}
+
+    /// The name of a column, in a column reference, definition or list
+    fn visit_column_name(&mut self, _name: &Token<'a>) {}
+}
+
+pub fn walk_statement<'a, V: Visitor<'a> + ?Sized>(v: &mut V, statement: &Statement<'a>) {
+    match *statement {
+        Statement::Select(ref s) => v.visit_select(s),
+        Statement::Insert(ref s) => v.visit_insert(s),
+        Statement::CreateTable(ref s) => v.visit_create_table(s),
+        Statement::DropTable(ref s) => v.visit_drop_table(s),
+    }
+}
+
+pub fn walk_select<'a, V: Visitor<'a> + ?Sized>(v: &mut V, select: &Select<'a>) {
+    for column in &select.columns {
+        v.visit_column(column);
+    }
+    v.visit_table_name(&select.table.token);
+    if let Some(ref predicate) = select.predicate {
+        v.visit_expr(predicate);
+    }
+    for order in &select.order_by {
+        v.visit_order_by(order);
+    }
+}
+
+pub fn walk_insert<'a, V: Visitor<'a> + ?Sized>(v: &mut V, insert: &Insert<'a>) {
+    v.visit_table_name(&insert.table.token);
+    for name in &insert.columns {
+        v.visit_column_name(&name.token);
+    }
+    for expr in insert.rows.iter().flat_map(|row| row.iter()) {
+        v.visit_expr(expr);
+    }
+}
+
+pub fn walk_create_table<'a, V: Visitor<'a> + ?Sized>(v: &mut V, create: &CreateTable<'a>) {
+    v.visit_table_name(&create.name.token);
+    for def in &create.columns {
+        v.visit_column_def(def);
+    }
+}
+
+pub fn walk_drop_table<'a, V: Visitor<'a> + ?Sized>(v: &mut V, drop: &DropTable<'a>) {
+    for name in &drop.names {
+        v.visit_table_name(&name.token);
+    }
+}
+
+pub fn walk_column<'a, V: Visitor<'a> + ?Sized>(v: &mut V, column: &Column<'a>) {
+    match *column {
+        Column::All(_) => (),
+        Column::AllFrom(ref table, _) => v.visit_table_name(table),
+        Column::Expr(ref expr, _) => v.visit_expr(expr),
+    }
+}
+
+pub fn walk_order_by<'a, V: Visitor<'a> + ?Sized>(v: &mut V, order: &OrderBy<'a>) {
+    v.visit_expr(&order.expr)
+}
+
+pub fn walk_column_def<'a, V: Visitor<'a> + ?Sized>(v: &mut V, def: &ColumnDef<'a>) {
+    v.visit_column_name(&def.name.token);
+    if let Some(ref default) = def.default {
+        v.visit_expr(default);
+    }
+}
+
+pub fn walk_expr<'a, V: Visitor<'a> + ?Sized>(v: &mut V, expr: &Expr<'a>) {
+    match expr.kind {
+        ExprKind::Column(ref name) => v.visit_column_name(name),
+        ExprKind::QualifiedColumn(ref table, ref name) => {
+            v.visit_table_name(table);
+            v.visit_column_name(name);
+        }
+        ExprKind::Unary(_, ref e) | ExprKind::Nested(ref e) => v.visit_expr(e),
+        ExprKind::Binary(ref l, _, ref r) => {
+            v.visit_expr(l);
+            v.visit_expr(r);
+        }
+        ExprKind::Literal(_) | ExprKind::Placeholder(_) => (),
+    }
+}
+
+pub trait VisitorMut<'a> {
+    fn visit_statement_mut(&mut self, statement: &mut Statement<'a>) {
+        walk_statement_mut(self, statement)
+    }
+
+    fn visit_select_mut(&mut self, select: &mut Select<'a>) {
+        walk_select_mut(self, select)
+    }
+
+    fn visit_insert_mut(&mut self, insert: &mut Insert<'a>) {
+        walk_insert_mut(self, insert)
+    }
+
+    fn visit_create_table_mut(&mut self, create: &mut CreateTable<'a>) {
+        walk_create_table_mut(self, create)
+    }
+
+    fn visit_drop_table_mut(&mut self, drop: &mut DropTable<'a>) {
+        walk_drop_table_mut(self, drop)
+    }
+
+    /// A column in the projection of a `SELECT`
+    fn visit_column_mut(&mut self, column: &mut Column<'a>) {
+        walk_column_mut(self, column)
+    }
+
+    fn visit_order_by_mut(&mut self, order: &mut OrderBy<'a>) {
+        walk_order_by_mut(self, order)
+    }
+
+    fn visit_column_def_mut(&mut self, def: &mut ColumnDef<'a>) {
+        walk_column_def_mut(self, def)
+    }
+
+    fn visit_expr_mut(&mut self, expr: &mut Expr<'a>) {
+        walk_expr_mut(self, expr)
+    }
+
+    /// The name of a table, or of the table qualifying a column reference
+    fn visit_table_name_mut(&mut self, _name: &mut Token<'a>) {}
+
+    /// The name of a column, in a column reference, definition or list
+    fn visit_column_name_mut(&mut self, _name: &mut Token<'a>) {}
+}
+
+pub fn walk_statement_mut<'a, V: VisitorMut<'a> + ?Sized>(
+    v: &mut V,
+    statement: &mut Statement<'a>,
+) {
+    match *statement {
+        Statement::Select(ref mut s) => v.visit_select_mut(s),
+        Statement::Insert(ref mut s) => v.visit_insert_mut(s),
+        Statement::CreateTable(ref mut s) => v.visit_create_table_mut(s),
+        Statement::DropTable(ref mut s) => v.visit_drop_table_mut(s),
+    }
+}
+
+pub fn walk_select_mut<'a, V: VisitorMut<'a> + ?Sized>(v: &mut V, select: &mut Select<'a>) {
+    for column in &mut select.columns {
+        v.visit_column_mut(column);
+    }
+    v.visit_table_name_mut(&mut select.table.token);
+    if let Some(ref mut predicate) = select.predicate {
+        v.visit_expr_mut(predicate);
+    }
+    for order in &mut select.order_by {
+        v.visit_order_by_mut(order);
+    }
+}
+
+pub fn walk_insert_mut<'a, V: VisitorMut<'a> + ?Sized>(v: &mut V, insert: &mut Insert<'a>) {
+    v.visit_table_name_mut(&mut insert.table.token);
+    for name in &mut insert.columns {
+        v.visit_column_name_mut(&mut name.token);
+    }
+    for expr in insert.rows.iter_mut().flat_map(|row| row.iter_mut()) {
+        v.visit_expr_mut(expr);
+    }
+}
+
+pub fn walk_create_table_mut<'a, V: VisitorMut<'a> + ?Sized>(
+    v: &mut V,
+    create: &mut CreateTable<'a>,
+) {
+    v.visit_table_name_mut(&mut create.name.token);
+    for def in &mut create.columns {
+        v.visit_column_def_mut(def);
+    }
+}
+
+pub fn walk_drop_table_mut<'a, V: VisitorMut<'a> + ?Sized>(v: &mut V, drop: &mut DropTable<'a>) {
+    for name in &mut drop.names {
+        v.visit_table_name_mut(&mut name.token);
+    }
+}
+
+pub fn walk_column_mut<'a, V: VisitorMut<'a> + ?Sized>(v: &mut V, column: &mut Column<'a>) {
+    match *column {
+        Column::All(_) => (),
+        Column::AllFrom(ref mut table, _) => v.visit_table_name_mut(table),
+        Column::Expr(ref mut expr, _) => v.visit_expr_mut(expr),
+    }
+}
+
+pub fn walk_order_by_mut<'a, V: VisitorMut<'a> + ?Sized>(v: &mut V, order: &mut OrderBy<'a>) {
+    v.visit_expr_mut(&mut order.expr)
+}
+
+pub fn walk_column_def_mut<'a, V: VisitorMut<'a> + ?Sized>(v: &mut V, def: &mut ColumnDef<'a>) {
+    v.visit_column_name_mut(&mut def.name.token);
+    if let Some(ref mut default) = def.default {
+        v.visit_expr_mut(default);
+    }
+}
+
+pub fn walk_expr_mut<'a, V: VisitorMut<'a> + ?Sized>(v: &mut V, expr: &mut Expr<'a>) {
+    match expr.kind {
+        ExprKind::Column(ref mut name) => v.visit_column_name_mut(name),
+        ExprKind::QualifiedColumn(ref mut table, ref mut name) => {
+            v.visit_table_name_mut(table);
+            v.visit_column_name_mut(name);
+        }
+        ExprKind::Unary(_, ref mut e) | ExprKind::Nested(ref mut e) => v.visit_expr_mut(e),
+        ExprKind::Binary(ref mut l, _, ref mut r) => {
+            v.visit_expr_mut(l);
+            v.visit_expr_mut(r);
+        }
+        ExprKind::Literal(_) | ExprKind::Placeholder(_) => (),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::expr::BinaryOp;
+    use super::super::super::parse_script;
+
+    /// Collects every table and column name that is referenced
+    #[derive(Default)]
+    struct Names {
+        tables: Vec<String>,
+        columns: Vec<String>,
+    }
+
+    impl<'a> Visitor<'a> for Names {
+        fn visit_table_name(&mut self, name: &Token<'a>) {
+            self.tables.push(name.to_string());
+        }
+
+        fn visit_column_name(&mut self, name: &Token<'a>) {
+            self.columns.push(name.to_string());
+        }
+    }
+
+    #[test]
+    fn visit_names() {
+        let statements = parse_script(
+            "create table t (a integer default b + 1);
+             insert into u (c) values (d);
+             select e, *, t.f from v where g > 1 order by h;
+             drop table w, x;",
+        ).unwrap();
+        let mut names = Names::default();
+        for statement in &statements {
+            names.visit_statement(statement);
+        }
+        assert_eq!(names.tables, vec!["t", "u", "t", "v", "w", "x"]);
+        assert_eq!(names.columns, vec!["a", "b", "c", "d", "e", "f", "g", "h"]);
+    }
+
+    /// Renames a table, and folds constant additions
+    struct Rewrite;
+
+    impl<'a> VisitorMut<'a> for Rewrite {
+        fn visit_table_name_mut(&mut self, name: &mut Token<'a>) {
+            if name.name() == Some("old") {
+                *name = Token::Identifier("new".into());
+            }
+        }
+
+        fn visit_expr_mut(&mut self, expr: &mut Expr<'a>) {
+            // Rewrite the children first, so that nested sums are folded
+            walk_expr_mut(self, expr);
+            let sum = match expr.kind {
+                ExprKind::Binary(ref l, BinaryOp::Add, ref r) => match (&l.kind, &r.kind) {
+                    (
+                        &ExprKind::Literal(Token::IntegerLiteral(a)),
+                        &ExprKind::Literal(Token::IntegerLiteral(b)),
+                    ) => a + b,
+                    _ => return,
+                },
+                _ => return,
+            };
+            expr.kind = ExprKind::Literal(Token::IntegerLiteral(sum));
+        }
+    }
+
+    #[test]
+    fn visit_rewrite() {
+        let mut statements =
+            parse_script("select old.a + (1 + 2 + 3) from old where b = 4 + 5").unwrap();
+        Rewrite.visit_statement_mut(&mut statements[0]);
+        assert_eq!(
+            statements[0].to_string(),
+            "SELECT new.a + (6) FROM new WHERE b = 9"
+        );
+    }
+}
@@ -1,12 +1,16 @@
 //! Lexical analysis module
 
 #![allow(dead_code)]
+use std::collections::VecDeque;
+use std::borrow::Cow;
+use std::io::{self, Read};
 use std::string::String;
-use std::mem;
+use std::{mem, str};
+use error::Error;
 use super::token::*;
 use super::parser::Parser;
 
-type LexerResult<T> = Result<T, String>;
+type LexerResult<T> = Result<T, Error>;
 
 #[derive(Debug, PartialEq, Clone)]
 enum State {
@@ -15,80 +19,260 @@ enum State {
     Number,
     Disambiguate,
     Comment,
+    // Inside of a block comment, nested to the given depth
+    BlockComment(usize, char),
     Operator,
-    Escape(bool),
+    // Just read a decimal point
+    Dot,
+    // Inside of a literal delimited by the given quote character
+    Quoted(char),
+    // Just read a quote character inside of a literal delimited by it
+    QuoteEnd(char),
+    // Just read a backslash inside of a backtick delimited literal
+    Backslash,
+    // Inside of a placeholder beginning with the given character
+    Parameter(char),
 }
 
 #[derive(Debug)]
 /// Finite state machine for lexical analysis of queries
-pub struct Lexer {
-    // List of tokens we have parsed
-    tokens: Vec<Token>,
+pub struct Lexer<'a> {
+    // Source text, if the whole of it is available. Words and literals are
+    // borrowed from the source rather than copied
+    source: Option<&'a str>,
+    // List of tokens we have parsed, and errors for those that were
+    // malformed, in source order
+    tokens: Vec<LexerResult<SpannedToken<'a>>>,
     // Last read character
     last_char: char,
-    // Word/number we are currently lexing
+    // Word/number we are currently lexing. This is only filled when the
+    // text cannot be borrowed from the source: when lexing from a reader, or
+    // once a literal turns out to contain escapes
     buffer: String,
+    // Whether the literal we are currently lexing contains escapes, and so
+    // differs from the source text
+    escaped: bool,
     // Current lexer state
     state: State,
     // Line number
     line: usize,
     // Column number
     column: usize,
+    // Byte offset of the next character
+    offset: usize,
+    // Location of the first character of the token we are currently lexing
+    start: Span,
+    // Empty span at the character being fed. Unlike the character's own
+    // span, a newline is placed at the end of the line it ends
+    current: Span,
 }
 
-impl Lexer {
-    /// Return an error message
+impl<'a> Lexer<'a> {
+    fn new(source: Option<&'a str>) -> Lexer<'a> {
+        Lexer {
+            source,
+            state: State::None,
+            tokens: Vec::new(),
+            last_char: ' ',
+            buffer: String::new(),
+            escaped: false,
+            column: 0,
+            line: 1,
+            offset: 0,
+            start: Span::default(),
+            current: Span::default(),
+        }
+    }
+
+    /// Save a token along with its location in the source
+    fn push(&mut self, token: Token<'a>, span: Span) {
+        // The token either ends just before the character being fed, or
+        // with it
+        let end = if span.end == self.current.start {
+            self.current
+        } else {
+            Span::new(self.offset, self.offset, self.line, self.column + 1)
+        };
+        self.tokens.push(Ok(SpannedToken { token, span, end }));
+    }
+
+    /// Save an error in place of a malformed token. Unlike an illegal
+    /// character, the character that ended the token is still lexed
+    fn reject(&mut self, error: Error) {
+        self.tokens.push(Err(error));
+    }
+
+    /// Return an error for the character that was just fed
     fn error(&self, c: char, expected: &str) -> LexerResult<State> {
-        Err(format!(
-            "Illegal character `{}` encountered on line {}, \
-            column {} during lexical analysis. Expected {}",
-            c,
-            self.line,
-            self.column,
-            expected
-        ))
+        Err(Error::IllegalCharacter {
+            found: c,
+            expected: expected.into(),
+            span: Span::new(
+                self.offset.saturating_sub(c.len_utf8()),
+                self.offset,
+                self.line,
+                self.column,
+            ),
+        })
     }
 
     /// Retrieve the last lexed token
-    fn last_token(&self) -> Option<Token> {
-        if self.tokens.len() > 0 {
-            Some(self.tokens[self.tokens.len() - 1].clone())
-        } else {
-            None
+    fn last_token(&self) -> Option<&Token<'a>> {
+        self.tokens.last().and_then(|tok| tok.as_ref().ok()).map(|tok| &tok.token)
+    }
+
+    /// Keep a character of the token being lexed, if its text cannot be
+    /// borrowed from the source
+    fn keep(&mut self, c: char) {
+        if self.source.is_none() || self.escaped {
+            self.buffer.push(c);
         }
     }
 
+    /// Note that the literal being lexed contains an escape starting at
+    /// byte offset `at`, so its text differs from the source. Any text read
+    /// so far is copied from the source, to be followed by the unescaped
+    /// characters
+    fn unescape(&mut self, at: usize) {
+        if let (Some(source), false) = (self.source, self.escaped) {
+            // Quote characters are a single byte
+            self.buffer.push_str(&source[self.start.start + 1..at]);
+        }
+        self.escaped = true;
+    }
+
+    /// Text of the word, number or placeholder being lexed, which is found
+    /// between byte offsets `start` and `end` of the source
+    fn text(&self, start: usize, end: usize) -> &str {
+        match self.source {
+            Some(source) => &source[start..end],
+            None => &self.buffer,
+        }
+    }
+
+    /// Take the text of the word or literal in the buffer, which is found
+    /// between byte offsets `start` and `end` of the source. The text is
+    /// borrowed from the source if it is available and was not unescaped
+    fn take_text(&mut self, start: usize, end: usize) -> Cow<'a, str> {
+        let text = match self.source {
+            Some(source) if !self.escaped => {
+                self.buffer.clear();
+                Cow::Borrowed(&source[start..end])
+            }
+            _ => Cow::Owned(mem::take(&mut self.buffer)),
+        };
+        self.escaped = false;
+        text
+    }
+
     /// Transition to the next state from State::None
     fn next_state(&self, c: char) -> LexerResult<State> {
         match c {
             // Identifiers and keywords must start with a letter or underscore
-            'a'...'z' | 'A'...'Z' | '_' => Ok(State::Text),
+            'a'..='z' | 'A'..='Z' | '_' => Ok(State::Text),
             // numbers must start with a number...
-            '0'...'9' => Ok(State::Number),
-            // Literals must start with a single apostrophe
-            '`' => Ok(State::Escape(false)),
+            '0'..='9' => Ok(State::Number),
+            // String literals are delimited by single quotes or backticks,
+            // and quoted identifiers by double quotes
+            '\'' | '"' | '`' => Ok(State::Quoted(c)),
             // Whitespace, return None
-            ' ' | '\t' | '\n' => Ok(State::None),
-            // Other UTF-8 character
-            ';' => Ok(State::None),
-            _ => {
-                let s = "<>|-+()[].,;*&|/=";
-                if let Some(index) = s.find(c) {
-                    if index < 4 {
-                        Ok(State::Disambiguate)
-                    } else {
-                        Ok(State::None)
-                    }
-                } else {
-                    self.error(c, "<>|-+()[].,*&|/=")
+            ' ' | '\t' | '\r' | '\n' => Ok(State::None),
+            // Characters that may begin a two character operator or comment
+            '<' | '>' | '|' | '-' | '!' | '/' => Ok(State::Disambiguate),
+            // Single character operators
+            '+' | '(' | ')' | '[' | ']' | '.' | ',' | ';' | '*' | '&' | '=' | '?' => Ok(State::None),
+            // Numbered and named placeholders
+            '$' | ':' => Ok(State::Parameter(c)),
+            _ => self.error(c, "one of <>|-+()[].,;*&/=!?$:"),
+        }
+    }
+
+    /// Handle a character from State::None, starting a new token
+    fn begin(&mut self, c: char, here: Span) -> LexerResult<State> {
+        // A leading decimal point may begin a number, so we need to see the
+        // next character before deciding
+        if c == '.' {
+            self.start = here;
+            return Ok(State::Dot);
+        }
+        let next = self.next_state(c)?;
+        match next {
+            State::None => {
+                if let Some(tok) = Token::from_char(c) {
+                    self.push(tok, here);
                 }
+            }
+            State::Comment | State::BlockComment(..) => (),
+            State::Text | State::Number => {
+                self.start = here;
+                self.keep(c);
+            }
+            State::Operator => match Token::from_char(c) {
+                Some(tok) => self.push(tok, here),
+                None => return self.error(c, "valid operator"),
+            },
+            _ => self.start = here,
+        };
+        Ok(next)
+    }
 
+    /// Save the literal in the buffer, which was delimited by `quote` and
+    /// ends at byte offset `end`
+    fn finish_quoted(&mut self, quote: char, end: usize) {
+        // Quote characters are a single byte
+        let word = self.take_text(self.start.start + 1, end - 1);
+        let span = self.start.extend_to(end);
+        let token = match quote {
+            // A string may be empty, but an identifier must name something
+            '"' if word.is_empty() => {
+                return self.reject(Error::IllegalCharacter {
+                    found: quote,
+                    expected: "identifier between quotes".into(),
+                    span,
+                })
             }
+            // Quoted identifiers preserve case
+            '"' => Token::QuotedIdentifier(word),
+            _ => Token::StringLiteral(word),
+        };
+        self.push(token, span);
+    }
+
+    /// Save the placeholder in the buffer, which began with `sigil` and ends
+    /// at byte offset `end`
+    fn finish_parameter(&mut self, sigil: char, end: usize) {
+        let span = self.start.extend_to(end);
+        // The sigil is a single byte
+        let text = self.text(self.start.start + 1, end);
+        if text.is_empty() {
+            let expected = if sigil == '$' { "parameter number" } else { "parameter name" };
+            return self.reject(Error::IllegalCharacter {
+                found: sigil,
+                expected: format!("{} after `{}`", expected, sigil),
+                span,
+            });
         }
+        let placeholder = if sigil == '$' {
+            let literal = format!("${}", text);
+            self.buffer.clear();
+            if !literal[1..].bytes().all(|b| b.is_ascii_digit()) {
+                return self.reject(Error::InvalidPlaceholder { literal, span });
+            }
+            match literal[1..].parse() {
+                Ok(0) => return self.reject(Error::InvalidPlaceholder { literal, span }),
+                Ok(i) => Placeholder::Index(i),
+                // Only digits were read, so the number must be too large
+                Err(_) => return self.reject(Error::NumberOverflow { literal, span }),
+            }
+        } else {
+            Placeholder::Named(self.take_text(self.start.start + 1, end))
+        };
+        self.push(Token::Placeholder(placeholder), span);
     }
 
     /// Feed a character into the lexer. Finite state machine
     fn feed(&mut self, c: char) -> LexerResult<State> {
+        self.current = Span::new(self.offset, self.offset, self.line, self.column + 1);
         // Update line and column number
         if c == '\n' {
             self.line += 1;
@@ -96,6 +280,8 @@ impl Lexer {
         } else {
             self.column += 1;
         };
+        let here = Span::new(self.offset, self.offset + c.len_utf8(), self.line, self.column);
+        self.offset = here.end;
 
         let state = match self.state {
             // Current state is comment, switch to None if newline
@@ -107,132 +293,150 @@ impl Lexer {
                 }
             }
             // Current state is none, so we are at the beginning, or whitespace
-            State::None => {
-                let next = self.next_state(c)?;
-                match next {
-                    State::None => {
-                        if let Some(tok) = Token::from_char(c) {
-                            self.tokens.push(tok);
-                        }
-                    }
-                    State::Comment => (),
-                    State::Text | State::Number => {
-                        self.buffer.push(c);
-                    }
-                    State::Operator => {
-                        self.tokens.push(Token::from_char(c).expect(&format!(
-                            "Illegal character `{}` encountered on line {},\
-                                 column {} during lexical analysis. Expected valid operator",
-                            c,
-                            self.line,
-                            self.column
-                        )));
-                    }
-                    _ => (),
-                };
-                next
-            }
+            State::None => self.begin(c, here)?,
             // Current state is text, so we are reading a string
             State::Text => {
-                match self.next_state(c)? {
-                    // Continue reading into buffer
-                    State::Text | State::Number => {
-                        self.buffer.push(c);
-                        State::Text
-                    }
-                    // Whitespace
-                    State::None => {
-                        let word: String = mem::replace(&mut self.buffer, String::new());
-                        self.tokens.push(Token::from_str(&word));
-                        if let Some(tok) = Token::from_char(c) {
-                            self.tokens.push(tok);
-                        }
-                        State::None
-                    }
-                    // Invalid character
-                    _ => return self.error(c, "valid identifier [a-Z|0-9|_]"),
+                if c.is_ascii_alphanumeric() || c == '_' {
+                    self.keep(c);
+                    State::Text
+                } else {
+                    // Any other character ends the word, and may start the
+                    // next token
+                    let word = self.take_text(self.start.start, here.start);
+                    let span = self.start.extend_to(here.start);
+                    self.push(Token::from_word(word), span);
+                    self.begin(c, here)?
                 }
             }
 
-            // Current state is number, so only acceptable chars are 0-9 and '.'
+            // Current state is number. Read everything that could plausibly
+            // be part of the number, and validate it once the number ends
             State::Number => {
-                match self.next_state(c)? {
-                    // Continue reading into buffer
-                    State::Number => {
-                        self.buffer.push(c);
-                        State::Number
-                    }
-                    // Check for decimal place
-                    State::Operator => {
-                        if c == '.' {
-                            self.buffer.push(c);
-                            State::Number
-                        } else {
-                            // Invalid character
-                            return self.error(c, "valid number [0-9|.]");
-                        }
-                    }
-                    State::None => {
-                        let word: String = mem::replace(&mut self.buffer, String::new());
-                        self.tokens.push(Token::NumberLiteral(word));
-                        if let Some(tok) = Token::from_char(c) {
-                            self.tokens.push(tok);
-                        }
-                        State::None
+                let text = self.text(self.start.start, here.start);
+                let hex = text.starts_with("0x") || text.starts_with("0X");
+                let exponent_sign = (c == '+' || c == '-') && !hex
+                    && (self.last_char == 'e' || self.last_char == 'E');
+                if c.is_ascii_alphanumeric() || c == '_' || c == '.' || exponent_sign {
+                    self.keep(c);
+                    State::Number
+                } else {
+                    let span = self.start.extend_to(here.start);
+                    match number(self.text(span.start, span.end), span) {
+                        Ok(tok) => self.push(tok, span),
+                        Err(e) => self.reject(e),
                     }
-                    // Invalid character
-                    _ => return self.error(c, "valid number [0-9|.]"),
+                    self.buffer.clear();
+                    self.begin(c, here)?
                 }
             }
-            // Reading literals, any UTF-8 character is valid except for backtick
-            State::Escape(escaped) => {
-                match (escaped, c) {
-                    (false, '`') => State::Escape(false),
-                    // This is a closing backtick
-                    (true, '`') => {
-                        // Was the backtick escaped? If not, then save the token
-                        if self.last_char != '\\' {
-                            let word: String = mem::replace(&mut self.buffer, String::new());
-                            self.tokens.push(Token::StringLiteral(word));
-                        }
-                        State::None
-                    }
-                    // Any character, any combination
-                    (_, _) => {
-                        self.buffer.push(c);
-                        State::Escape(true)
-                    }
+            // A decimal point, which may be the start of a number like `.5`
+            State::Dot => {
+                if c.is_ascii_digit() {
+                    self.keep('.');
+                    self.keep(c);
+                    State::Number
+                } else {
+                    self.push(Token::DOT, self.start);
+                    self.begin(c, here)?
+                }
+            }
+            // Reading literals, any UTF-8 character is valid except for the
+            // closing quote
+            State::Quoted(quote) => {
+                if quote == '`' && c == '\\' {
+                    State::Backslash
+                } else if c == quote && quote == '`' {
+                    self.finish_quoted(quote, here.end);
+                    State::None
+                } else if c == quote {
+                    State::QuoteEnd(quote)
+                } else {
+                    self.keep(c);
+                    State::Quoted(quote)
+                }
+            }
+            // Backslash escapes the next character in a backtick literal
+            State::Backslash => {
+                // The backslash is a single byte
+                self.unescape(here.start - 1);
+                self.buffer.push(c);
+                State::Quoted('`')
+            }
+            // Single and double quotes are escaped by doubling them, so we
+            // only know the literal is closed if the next character differs
+            State::QuoteEnd(quote) => {
+                if c == quote {
+                    // The quote before this one is a single byte
+                    self.unescape(here.start - 1);
+                    self.buffer.push(c);
+                    State::Quoted(quote)
+                } else {
+                    self.finish_quoted(quote, here.start);
+                    self.begin(c, here)?
                 }
             }
             // Operator or character that needs disambiguation
             State::Disambiguate => {
+                let pair = match (self.last_char, c) {
+                    ('<', '>') | ('!', '=') => Some(Token::NOTEQUAL),
+                    ('<', '=') => Some(Token::LESSTHANOREQUAL),
+                    ('>', '=') => Some(Token::GREATERTHANOREQUAL),
+                    ('|', '|') => Some(Token::DOUBLEPIPE),
+                    _ => None,
+                };
                 match (self.last_char, c) {
                     ('-', '-') => State::Comment,
-                    ('<', '>') => {
-                        self.tokens.push(Token::NOTEQUAL);
-                        State::None
-                    }
-                    ('<', '=') => {
-                        self.tokens.push(Token::LESSTHANOREQUAL);
-                        State::None
-                    }
-                    ('>', '=') => {
-                        self.tokens.push(Token::GREATERTHANOREQUAL);
-                        State::None
-                    }
-                    ('>', ' ') => {
-                        self.tokens.push(Token::GREATERTHAN);
-                        State::None
-                    }
-                    ('<', ' ') => {
-                        self.tokens.push(Token::LESSTHAN);
-                        State::None
-                    }
-                    ('|', '|') => {
-                        self.tokens.push(Token::DOUBLEPIPE);
-                        State::None
-                    }
-                    (_, _) => return self.error(c, "matching operator"),
+                    ('/', '*') => State::BlockComment(1, ' '),
+                    _ => match (pair, Token::from_char(self.last_char)) {
+                        (Some(tok), _) => {
+                            let span = self.start.extend_to(here.end);
+                            self.push(tok, span);
+                            State::None
+                        }
+                        // Not a two character operator, so the previous
+                        // character stands on its own and this one begins
+                        // the next token
+                        (None, Some(tok)) => {
+                            self.push(tok, self.start);
+                            self.begin(c, here)?
+                        }
+                        // A lone `!`, which is reported where it stands
+                        (None, None) => {
+                            self.reject(Error::IllegalCharacter {
+                                found: self.last_char,
+                                expected: "`=` following `!`".into(),
+                                span: self.start,
+                            });
+                            self.begin(c, here)?
+                        }
+                    },
+                }
+            }
+            // Block comments may be nested. The second element is the
+            // previous character inside of the comment
+            State::BlockComment(depth, prev) => match (prev, c) {
+                ('*', '/') if depth == 1 => State::None,
+                ('*', '/') => State::BlockComment(depth - 1, ' '),
+                ('/', '*') => State::BlockComment(depth + 1, ' '),
+                _ => State::BlockComment(depth, c),
+            },
+            // Placeholders are numbered by digits following a `$`, or named by
+            // word characters following a `:`. As with numbers, any word
+            // characters directly after the digits are read, and rejected
+            // once the placeholder ends
+            State::Parameter(sigil) => {
+                let valid = if sigil == '$' {
+                    c.is_ascii_digit()
+                        || (here.start > self.start.end && (c.is_ascii_alphanumeric() || c == '_'))
+                } else {
+                    c.is_ascii_alphanumeric() || c == '_'
+                };
+                if valid {
+                    self.keep(c);
+                    State::Parameter(sigil)
+                } else {
+                    self.finish_parameter(sigil, here.start);
+                    self.begin(c, here)?
                 }
             }
             // Operator. Token was already pushed, transition back to none
@@ -244,24 +448,268 @@ impl Lexer {
         Ok(state)
     }
 
-    pub fn lex(s: &str) -> LexerResult<Parser> {
-        let mut lex = Lexer {
-            state: State::None,
-            tokens: Vec::new(),
-            last_char: ' ',
-            buffer: String::new(),
-            column: 0,
-            line: 0,
-        };
+    /// Feed a character into the lexer. If it is illegal, abandon the token
+    /// that we were in the middle of and start again after it
+    fn feed_recovering(&mut self, c: char) -> LexerResult<()> {
+        if let Err(e) = self.feed(c) {
+            self.buffer.clear();
+            self.escaped = false;
+            self.state = State::None;
+            self.last_char = c;
+            return Err(e);
+        }
+        Ok(())
+    }
 
-        for c in s.chars() {
-            lex.feed(c)?;
+    /// Flush any word or number left in the buffer at the end of input, or
+    /// report a literal or comment that was never closed
+    fn finish(&mut self) -> LexerResult<()> {
+        match self.state {
+            State::Quoted(_) | State::Backslash => Err(Error::UnterminatedLiteral {
+                span: self.start.extend_to(self.offset),
+            }),
+            State::BlockComment(..) => Err(Error::UnterminatedComment {
+                span: self.start.extend_to(self.offset),
+            }),
+            _ => self.feed(' ').map(|_| ()),
+        }
+    }
+
+    /// Lazily lex a string. Illegal characters are returned as errors,
+    /// and lexing continues after them
+    pub fn tokens(s: &'a str) -> impl Iterator<Item = LexerResult<SpannedToken<'a>>> + 'a {
+        Tokens::new(Lexer::new(Some(s)), s.chars().map(Ok))
+    }
+
+    /// Lazily lex UTF-8 text from a reader, which is read in chunks as
+    /// tokens are requested. Illegal characters are returned as errors, and
+    /// lexing continues after them. Lexing stops after a read error or
+    /// invalid UTF-8
+    pub fn from_reader<R: Read + 'a>(
+        reader: R,
+    ) -> impl Iterator<Item = LexerResult<SpannedToken<'a>>> + 'a {
+        Tokens::new(Lexer::new(None), Chars::new(reader))
+    }
+
+    pub fn lex(s: &'a str) -> LexerResult<Parser<'a>> {
+        let (parser, mut errors) = Lexer::lex_recovering(s);
+        if errors.is_empty() {
+            Ok(parser)
+        } else {
+            Err(errors.remove(0))
+        }
+    }
+
+    /// Lex the entire input, skipping over any illegal characters rather
+    /// than stopping at the first one. Every error encountered is returned
+    /// alongside the tokens that could be lexed
+    pub fn lex_recovering(s: &'a str) -> (Parser<'a>, Vec<Error>) {
+        let mut tokens = Vec::new();
+        let mut errors = Vec::new();
+        for result in Lexer::tokens(s) {
+            match result {
+                Ok(tok) => tokens.push(tok),
+                Err(e) => errors.push(e),
+            }
+        }
+        (Parser::from_tokens(tokens), errors)
+    }
+}
+
+/// Iterator that feeds characters into a lexer on demand, returning tokens
+/// as soon as they are complete
+struct Tokens<'a, C> {
+    lex: Lexer<'a>,
+    chars: C,
+    // Tokens and errors that have been lexed, but not yet returned
+    pending: VecDeque<LexerResult<SpannedToken<'a>>>,
+    done: bool,
+}
+
+impl<'a, C: Iterator<Item = io::Result<char>>> Tokens<'a, C> {
+    fn new(lex: Lexer<'a>, chars: C) -> Tokens<'a, C> {
+        Tokens {
+            lex,
+            chars,
+            pending: VecDeque::new(),
+            done: false,
+        }
+    }
+}
+
+impl<'a, C: Iterator<Item = io::Result<char>>> Iterator for Tokens<'a, C> {
+    type Item = LexerResult<SpannedToken<'a>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.pending.is_empty() && !self.done {
+            let result = match self.chars.next() {
+                Some(Ok(c)) => self.lex.feed_recovering(c),
+                Some(Err(e)) => {
+                    self.done = true;
+                    let here = Span::new(
+                        self.lex.offset,
+                        self.lex.offset,
+                        self.lex.line,
+                        self.lex.column + 1,
+                    );
+                    Err(Error::Io {
+                        message: e.to_string(),
+                        span: here,
+                    })
+                }
+                None => {
+                    self.done = true;
+                    self.lex.finish()
+                }
+            };
+            // A character may complete a token before turning out to be
+            // illegal itself, so tokens come before the error
+            self.pending.extend(self.lex.tokens.drain(..));
+            if let Err(e) = result {
+                self.pending.push_back(Err(e));
+            }
+        }
+        self.pending.pop_front()
+    }
+}
+
+/// Size of the chunks that are read from a reader at a time
+const CHUNK_SIZE: usize = 8 * 1024;
+
+/// Iterator that decodes UTF-8 characters from a reader, one chunk at a time
+struct Chars<R> {
+    reader: R,
+    buf: Vec<u8>,
+    // Range of `buf` that has been read but not yet decoded
+    pos: usize,
+    len: usize,
+}
+
+impl<R: Read> Chars<R> {
+    fn new(reader: R) -> Chars<R> {
+        Chars {
+            reader,
+            buf: vec![0; CHUNK_SIZE],
+            pos: 0,
+            len: 0,
+        }
+    }
+
+    /// Make at least `n` undecoded bytes available, unless the reader runs
+    /// out first. Returns the number of bytes available
+    fn fill(&mut self, n: usize) -> io::Result<usize> {
+        if self.pos + n > self.buf.len() {
+            self.buf.copy_within(self.pos..self.len, 0);
+            self.len -= self.pos;
+            self.pos = 0;
+        }
+        while self.len - self.pos < n {
+            match self.reader.read(&mut self.buf[self.len..]) {
+                Ok(0) => break,
+                Ok(read) => self.len += read,
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => (),
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(self.len - self.pos)
+    }
+
+    fn decode(&mut self) -> io::Result<Option<char>> {
+        if self.fill(1)? == 0 {
+            return Ok(None);
+        }
+        // The leading byte determines the length of the encoded character
+        let width = match self.buf[self.pos] {
+            0x00..=0x7f => 1,
+            0xc0..=0xdf => 2,
+            0xe0..=0xef => 3,
+            _ => 4,
+        };
+        let invalid = || {
+            io::Error::new(io::ErrorKind::InvalidData, "stream did not contain valid UTF-8")
+        };
+        if self.fill(width)? < width {
+            return Err(invalid());
         }
-        Ok(Parser::from_tokens(lex.tokens))
+        let c = str::from_utf8(&self.buf[self.pos..self.pos + width])
+            .map_err(|_| invalid())?
+            .chars()
+            .next();
+        self.pos += width;
+        Ok(c)
     }
 }
 
+impl<R: Read> Iterator for Chars<R> {
+    type Item = io::Result<char>;
 
+    fn next(&mut self) -> Option<io::Result<char>> {
+        self.decode().transpose()
+    }
+}
+
+/// Validate a numeric literal, and convert it into an integer or float token
+///
+/// Integers are a run of decimal digits, or `0x` followed by hexadecimal
+/// digits. Floats have a decimal point, an exponent, or both: `1.5`, `.5`,
+/// `1.`, `1e10`, `2.5E-3`
+/// Magnitude of `i64::MIN`, one more than `i64::MAX`
+const MIN_MAGNITUDE: u64 = 1 << 63;
+
+fn number(word: &str, span: Span) -> LexerResult<Token<'static>> {
+    let malformed = || Error::MalformedNumber {
+        literal: word.into(),
+        span,
+    };
+    let overflow = || Error::NumberOverflow {
+        literal: word.into(),
+        span,
+    };
+
+    if word.starts_with("0x") || word.starts_with("0X") {
+        let digits = &word[2..];
+        if digits.is_empty() || !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(malformed());
+        }
+        return i64::from_str_radix(digits, 16)
+            .map(Token::IntegerLiteral)
+            .map_err(|_| overflow());
+    }
+
+    let (mantissa, exponent) = match word.find(['e', 'E']) {
+        Some(i) => (&word[..i], Some(&word[i + 1..])),
+        None => (word, None),
+    };
+    let mantissa_ok = mantissa.chars().all(|c| c.is_ascii_digit() || c == '.')
+        && mantissa.chars().filter(|&c| c == '.').count() <= 1
+        && mantissa.chars().any(|c| c.is_ascii_digit());
+    let exponent_ok = match exponent {
+        Some(e) => {
+            let digits = e.trim_start_matches(['+', '-']);
+            e.len() - digits.len() <= 1 && !digits.is_empty()
+                && digits.chars().all(|c| c.is_ascii_digit())
+        }
+        None => true,
+    };
+    if !mantissa_ok || !exponent_ok {
+        return Err(malformed());
+    }
+
+    if mantissa.contains('.') || exponent.is_some() {
+        match word.parse::<f64>() {
+            Ok(x) if x.is_infinite() => Err(overflow()),
+            Ok(x) => Ok(Token::FloatLiteral(x)),
+            Err(_) => Err(malformed()),
+        }
+    } else {
+        match word.parse::<u64>() {
+            // Only valid when negated, which the parser checks
+            Ok(MIN_MAGNITUDE) => Ok(Token::IntegerLiteral(i64::MIN)),
+            Ok(n) if n < MIN_MAGNITUDE => Ok(Token::IntegerLiteral(n as i64)),
+            _ => Err(overflow()),
+        }
+    }
+}
 
 #[cfg(test)]
 mod tests {
@@ -278,7 +726,7 @@ mod tests {
             Token::WHERE,
             Token::Identifier("row_id".into()),
             Token::GREATERTHAN,
-            Token::NumberLiteral("0".into()),
+            Token::IntegerLiteral(0),
             Token::SEMICOLON,
         ];
         for tok in v.into_iter() {
@@ -289,18 +737,12 @@ mod tests {
     #[test]
     /// Test state transitions from State::None -> State::_
     fn next_state() {
-        let lex = Lexer {
-            state: State::None,
-            tokens: Vec::new(),
-            last_char: ' ',
-            buffer: String::new(),
-            column: 0,
-            line: 0,
-        };
+        let lex = Lexer::new(None);
         assert_eq!(lex.next_state('.'), Ok(State::None));
         assert_eq!(lex.next_state('a'), Ok(State::Text));
         assert_eq!(lex.next_state('9'), Ok(State::Number));
-        assert_eq!(lex.next_state('`'), Ok(State::Escape(false)));
+        assert_eq!(lex.next_state('`'), Ok(State::Quoted('`')));
+        assert_eq!(lex.next_state('\''), Ok(State::Quoted('\'')));
         assert_eq!(lex.next_state('<'), Ok(State::Disambiguate));
         assert_eq!(lex.next_state('='), Ok(State::None));
     }
@@ -308,59 +750,119 @@ mod tests {
     #[test]
     /// Test lexing of an identifier
     fn feed_identifier() {
-        let mut lex = Lexer {
-            state: State::None,
-            tokens: Vec::new(),
-            last_char: ' ',
-            buffer: String::new(),
-            column: 0,
-            line: 0,
-        };
+        let mut lex = Lexer::new(None);
 
         let s = "my_table";
         for c in s.chars() {
             assert_eq!(lex.feed(c), Ok(State::Text));
         }
         assert_eq!(lex.feed(' '), Ok(State::None));
-        assert_eq!(lex.tokens.pop(), Some(Token::Identifier(s.into())));
+        assert_eq!(lex.tokens.pop().map(|t| t.unwrap().token), Some(Token::Identifier(s.into())));
         assert_eq!(lex.state, State::None);
     }
 
     #[test]
     /// Test lexing of a literal
     fn feed_literal() {
-        let mut lex = Lexer {
-            state: State::None,
-            tokens: Vec::new(),
-            last_char: ' ',
-            buffer: String::new(),
-            column: 0,
-            line: 0,
-        };
+        let mut lex = Lexer::new(None);
 
         // Try lexing a string literal
-        assert_eq!(lex.feed('`'), Ok(State::Escape(false)));
+        assert_eq!(lex.feed('`'), Ok(State::Quoted('`')));
         for c in "user_id".chars() {
-            assert_eq!(lex.feed(c), Ok(State::Escape(true)));
+            assert_eq!(lex.feed(c), Ok(State::Quoted('`')));
         }
         assert_eq!(lex.feed('`'), Ok(State::None));
         assert_eq!(
-            lex.tokens.pop(),
+            lex.tokens.pop().map(|t| t.unwrap().token),
             Some(Token::StringLiteral("user_id".into()))
         );
         assert_eq!(lex.column, 9);
     }
 
+    #[test]
+    fn lex_quoted() {
+        let mut parser = Lexer::lex(
+            "'it''s', '', \"UserId\", \"say \"\"hi\"\"\", `a\\`b`, ``",
+        ).unwrap();
+        let v = vec![
+            Token::StringLiteral("it's".into()),
+            Token::StringLiteral("".into()),
+            Token::QuotedIdentifier("UserId".into()),
+            Token::QuotedIdentifier("say \"hi\"".into()),
+            Token::StringLiteral("a`b".into()),
+            Token::StringLiteral("".into()),
+        ];
+        for tok in v.into_iter() {
+            parser.expect(&tok).unwrap();
+            parser.pop_if(&Token::COMMA);
+        }
+        assert_eq!(parser.peek(), None);
+    }
+
+    #[test]
+    fn lex_quoted_adjacent() {
+        // A closing quote may be followed immediately by another token
+        let mut parser = Lexer::lex("('a')").unwrap();
+        parser.expect(&Token::LEFTPAREN).unwrap();
+        let span = parser.peek_span();
+        parser.expect(&Token::StringLiteral("a".into())).unwrap();
+        parser.expect(&Token::RIGHTPAREN).unwrap();
+        assert_eq!((span.start, span.end), (1, 4));
+
+        assert!(Lexer::lex("'abc''").is_err());
+        assert!(Lexer::lex("`abc\\`").is_err());
+    }
+
+    #[test]
+    fn lex_numbers() {
+        let mut parser = Lexer::lex(
+            "42 0x1F 0XfF 1.5 .5 1. 1e10 2.5E-3 7e+2 9223372036854775807 t.c (.25) \
+             9223372036854775808",
+        ).unwrap();
+        let v = vec![
+            Token::IntegerLiteral(42),
+            Token::IntegerLiteral(31),
+            Token::IntegerLiteral(255),
+            Token::FloatLiteral(1.5),
+            Token::FloatLiteral(0.5),
+            Token::FloatLiteral(1.0),
+            Token::FloatLiteral(1e10),
+            Token::FloatLiteral(2.5e-3),
+            Token::FloatLiteral(700.0),
+            Token::IntegerLiteral(i64::MAX),
+            Token::Identifier("t".into()),
+            Token::DOT,
+            Token::Identifier("c".into()),
+            Token::LEFTPAREN,
+            Token::FloatLiteral(0.25),
+            Token::RIGHTPAREN,
+            Token::IntegerLiteral(i64::MIN),
+        ];
+        for tok in v.into_iter() {
+            parser.expect(&tok).unwrap();
+        }
+        assert_eq!(parser.peek(), None);
+    }
+
+    #[test]
+    fn lex_number_errors() {
+        for s in &["1.2.3", "123abc", "0x", "0x1G", "1e", "1e+", "1_000"] {
+            match Lexer::lex(s) {
+                Err(Error::MalformedNumber { ref literal, .. }) => assert_eq!(literal, s),
+                e => panic!("Expected malformed {}, found {:?}", s, e.map(|_| ())),
+            }
+        }
+        for s in &["9223372036854775809", "0x8000000000000000", "1e400"] {
+            match Lexer::lex(s) {
+                Err(Error::NumberOverflow { ref literal, .. }) => assert_eq!(literal, s),
+                e => panic!("Expected overflow {}, found {:?}", s, e.map(|_| ())),
+            }
+        }
+    }
+
     #[test]
     fn feed_comment() {
-        let mut lex = Lexer {
-            state: State::None,
-            tokens: Vec::new(),
-            last_char: ' ',
-            buffer: String::new(),
-            column: 0,
-            line: 0,
-        };
+        let mut lex = Lexer::new(None);
         assert_eq!(lex.feed('-'), Ok(State::Disambiguate));
         assert_eq!(lex.feed('-'), Ok(State::Comment));
         for c in "line comment".chars() {
@@ -369,28 +871,383 @@ mod tests {
         assert_eq!(lex.feed('\n'), Ok(State::None));
     }
 
+    #[test]
+    /// Test that tokens are tagged with their location in the source
+    fn feed_spans() {
+        let mut lex = Lexer::new(None);
+        for c in "a >= 10\n  `x y`".chars() {
+            lex.feed(c).unwrap();
+        }
+        lex.feed(' ').unwrap();
+        let spans: Vec<Span> = lex.tokens.iter().map(|t| t.as_ref().unwrap().span).collect();
+        assert_eq!(
+            spans,
+            vec![
+                Span::new(0, 1, 1, 1),
+                Span::new(2, 4, 1, 3),
+                Span::new(5, 7, 1, 6),
+                Span::new(10, 15, 2, 3),
+            ]
+        );
+    }
+
+    #[test]
+    fn lex_errors() {
+        match Lexer::lex("select #") {
+            Err(Error::IllegalCharacter { found, span, .. }) => {
+                assert_eq!(found, '#');
+                assert_eq!(span, Span::new(7, 8, 1, 8));
+            }
+            e => panic!("Expected illegal character, found {:?}", e.map(|_| ())),
+        }
+        match Lexer::lex("create table \"\" (a int)") {
+            Err(Error::IllegalCharacter { found, span, .. }) => {
+                assert_eq!(found, '"');
+                assert_eq!(span, Span::new(13, 15, 1, 14));
+            }
+            e => panic!("Expected illegal character, found {:?}", e.map(|_| ())),
+        }
+        match Lexer::lex("select `abc") {
+            Err(Error::UnterminatedLiteral { span }) => {
+                assert_eq!(span, Span::new(7, 11, 1, 8));
+            }
+            e => panic!("Expected unterminated literal, found {:?}", e.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn lex_recovering() {
+        let (mut parser, errors) = Lexer::lex_recovering("a # b @ c");
+        assert_eq!(errors.len(), 2);
+        assert_eq!(errors[1].span().start, 6);
+        for tok in &["a", "b", "c"] {
+            parser.expect(&Token::Identifier((*tok).into())).unwrap();
+        }
+        assert_eq!(parser.peek(), None);
+    }
+
     #[test]
     fn feed_statement() {
-        let mut lex = Lexer {
-            state: State::None,
-            tokens: Vec::new(),
-            last_char: ' ',
-            buffer: String::new(),
-            column: 0,
-            line: 0,
-        };
+        let mut lex = Lexer::new(None);
 
         let query = "SELECT * FROM my_table WHERE name = `user1`";
         for c in query.chars() {
             lex.feed(c).unwrap();
         }
-        assert_eq!(lex.tokens.pop(), Some(Token::StringLiteral("user1".into())));
-        assert_eq!(lex.tokens.pop(), Some(Token::EQUAL));
-        assert_eq!(lex.tokens.pop(), Some(Token::Identifier("name".into())));
-        assert_eq!(lex.tokens.pop(), Some(Token::WHERE));
-        assert_eq!(lex.tokens.pop(), Some(Token::Identifier("my_table".into())));
-        assert_eq!(lex.tokens.pop(), Some(Token::FROM));
-        assert_eq!(lex.tokens.pop(), Some(Token::ASTERISK));
-        assert_eq!(lex.tokens.pop(), Some(Token::SELECT));
+        assert_eq!(
+            lex.tokens.pop().map(|t| t.unwrap().token),
+            Some(Token::StringLiteral("user1".into()))
+        );
+        assert_eq!(lex.tokens.pop().map(|t| t.unwrap().token), Some(Token::EQUAL));
+        assert_eq!(lex.tokens.pop().map(|t| t.unwrap().token), Some(Token::Identifier("name".into())));
+        assert_eq!(lex.tokens.pop().map(|t| t.unwrap().token), Some(Token::WHERE));
+        assert_eq!(
+            lex.tokens.pop().map(|t| t.unwrap().token),
+            Some(Token::Identifier("my_table".into()))
+        );
+        assert_eq!(lex.tokens.pop().map(|t| t.unwrap().token), Some(Token::FROM));
+        assert_eq!(lex.tokens.pop().map(|t| t.unwrap().token), Some(Token::ASTERISK));
+        assert_eq!(lex.tokens.pop().map(|t| t.unwrap().token), Some(Token::SELECT));
+    }
+
+    fn tokens<'a>(s: &'a str) -> Vec<Token<'a>> {
+        let mut parser = Lexer::lex(s).unwrap();
+        let mut v = Vec::new();
+        while parser.peek().is_some() {
+            v.push(parser.pop().unwrap());
+        }
+        v
+    }
+
+    #[test]
+    fn lex_adjacent_operators() {
+        let ident = |s: &'static str| Token::Identifier(s.into());
+        assert_eq!(
+            tokens("a>=b"),
+            vec![ident("a"), Token::GREATERTHANOREQUAL, ident("b")]
+        );
+        assert_eq!(
+            tokens("x-1"),
+            vec![ident("x"), Token::MINUS, Token::IntegerLiteral(1)]
+        );
+        assert_eq!(tokens("a!=b"), tokens("a <> b"));
+        assert_eq!(tokens("a<>b"), vec![ident("a"), Token::NOTEQUAL, ident("b")]);
+        assert_eq!(
+            tokens("(a<b)||c"),
+            vec![
+                Token::LEFTPAREN,
+                ident("a"),
+                Token::LESSTHAN,
+                ident("b"),
+                Token::RIGHTPAREN,
+                Token::DOUBLEPIPE,
+                ident("c"),
+            ]
+        );
+        assert_eq!(
+            tokens("a/-b"),
+            vec![ident("a"), Token::FORWARDSLASH, Token::MINUS, ident("b")]
+        );
+        match Lexer::lex("a ! b") {
+            Err(Error::IllegalCharacter { found, span, .. }) => {
+                assert_eq!(found, '!');
+                assert_eq!(span, Span::new(2, 3, 1, 3));
+            }
+            e => panic!("Expected illegal character, found {:?}", e.map(|_| ())),
+        }
+        // At the end of input, the error still points at the `!`
+        let (mut parser, errors) = Lexer::lex_recovering("select a from t !");
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].span(), Span::new(16, 17, 1, 17));
+        for tok in tokens("select a from t") {
+            parser.expect(&tok).unwrap();
+        }
+        assert_eq!(parser.peek(), None);
+    }
+
+    #[test]
+    fn lex_block_comments() {
+        assert_eq!(
+            tokens("a /* one /* two */ still one */ b/**/c"),
+            vec![
+                Token::Identifier("a".into()),
+                Token::Identifier("b".into()),
+                Token::Identifier("c".into()),
+            ]
+        );
+        // Outside of a comment, `*/` is just two operators
+        assert_eq!(tokens("2*/3"), tokens("2 * / 3"));
+        assert_eq!(tokens("2*/*x*/3"), tokens("2 * 3"));
+        match Lexer::lex("a /* /* */ b") {
+            Err(Error::UnterminatedComment { span }) => {
+                assert_eq!(span, Span::new(2, 12, 1, 3));
+            }
+            e => panic!("Expected unterminated comment, found {:?}", e.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn lex_crlf() {
+        let (mut parser, errors) = Lexer::lex_recovering("select a\r\nfrom b -- note\r\n;");
+        assert!(errors.is_empty());
+        parser.expect(&Token::SELECT).unwrap();
+        parser.expect(&Token::Identifier("a".into())).unwrap();
+        assert_eq!(parser.peek_span(), Span::new(10, 14, 2, 1));
+        parser.expect(&Token::FROM).unwrap();
+        parser.expect(&Token::Identifier("b".into())).unwrap();
+        assert_eq!(parser.peek_span(), Span::new(26, 27, 3, 1));
+    }
+
+    /// Small xorshift generator, so the property test below is repeatable
+    struct Rng(u64);
+
+    impl Rng {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        fn below(&mut self, n: usize) -> usize {
+            (self.next() % n as u64) as usize
+        }
+    }
+
+    /// Would the lexer read `a` followed directly by `b` differently than
+    /// if they were separated by whitespace
+    fn merges(a: char, b: char) -> bool {
+        let word = |c: char| c.is_ascii_alphanumeric() || c == '_';
+        (word(a) && word(b))
+            || (a.is_ascii_digit() && b == '.')
+            || (a == '.' && b.is_ascii_digit())
+            || (a == b && "'\"`".contains(a))
+            || ["<>", "<=", ">=", "!=", "||", "--", "/*"]
+                .iter()
+                .any(|pair| pair.starts_with(a) && pair.ends_with(b))
+    }
+
+    #[test]
+    /// Render random token sequences with random spacing and comments, and
+    /// check that lexing gives back the original tokens
+    fn lex_reference_tokenization() {
+        let fixed = [
+            Token::SELECT, Token::FROM, Token::WHERE, Token::AND, Token::NOT, Token::NULL,
+            Token::INTEGER, Token::EQUAL, Token::NOTEQUAL, Token::LESSTHAN,
+            Token::LESSTHANOREQUAL, Token::GREATERTHAN, Token::GREATERTHANOREQUAL,
+            Token::PLUS, Token::MINUS, Token::FORWARDSLASH, Token::ASTERISK, Token::LEFTPAREN,
+            Token::RIGHTPAREN, Token::DOT, Token::COMMA, Token::SEMICOLON, Token::PIPE,
+            Token::DOUBLEPIPE,
+        ];
+        let separators = ["", " ", "\t", "\r\n", "/* c /* n */ */", "-- c\n"];
+
+        let mut rng = Rng(0x2545_f491_4f6c_dd1d);
+        for _ in 0..500 {
+            let mut expected = Vec::new();
+            let mut source = String::new();
+            for _ in 0..rng.below(20) {
+                let n = rng.below(100);
+                let tok = match rng.below(6) {
+                    0 => Token::Identifier(format!("col{}", n).into()),
+                    1 => Token::QuotedIdentifier(format!("Col \"{}\"", n).into()),
+                    2 => Token::StringLiteral(format!("it's {}", n).into()),
+                    3 => Token::IntegerLiteral(n as i64),
+                    4 => Token::FloatLiteral(n as f64 / 4.0),
+                    _ => fixed[rng.below(fixed.len())].clone(),
+                };
+                let pieces = [separators[rng.below(separators.len())].to_string(), tok.to_string()];
+                for piece in &pieces {
+                    match (source.chars().last(), piece.chars().next()) {
+                        (Some(a), Some(b)) if merges(a, b) => source.push(' '),
+                        _ => (),
+                    }
+                    source.push_str(piece);
+                }
+                expected.push(tok);
+            }
+            assert_eq!(tokens(&source), expected, "lexing {:?}", source);
+        }
+    }
+
+    /// Reader that returns a single byte at a time
+    struct Trickle<'a>(&'a [u8]);
+
+    impl<'a> Read for Trickle<'a> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            (&mut self.0).take(1).read(buf)
+        }
+    }
+
+    #[test]
+    fn lex_reader() {
+        let s = "select 'héllo ✓', \"Ünïcode\" from t -- ✓✓\nwhere a>=1.5;";
+        let expected: Vec<LexerResult<SpannedToken>> = Lexer::tokens(s).collect();
+        assert_eq!(expected.len(), 11);
+        assert_eq!(Lexer::from_reader(Trickle(s.as_bytes())).collect::<Vec<_>>(), expected);
+        assert_eq!(Lexer::from_reader(s.as_bytes()).collect::<Vec<_>>(), expected);
+
+        // Tokens are returned without reading the rest of the input
+        let endless = "select 1;".as_bytes().chain(io::repeat(b'a'));
+        let tokens: Vec<Token> = Lexer::from_reader(endless)
+            .take(3)
+            .map(|tok| tok.unwrap().token)
+            .collect();
+        assert_eq!(tokens, vec![Token::SELECT, Token::IntegerLiteral(1), Token::SEMICOLON]);
+    }
+
+    #[test]
+    fn lex_reader_invalid_utf8() {
+        let mut tokens = Lexer::from_reader(&b"a \xe2\x9c b"[..]);
+        assert_eq!(tokens.next().map(|t| t.unwrap().token), Some(Token::Identifier("a".into())));
+        match tokens.next() {
+            Some(Err(Error::Io { span, .. })) => assert_eq!(span, Span::new(2, 2, 1, 3)),
+            e => panic!("Expected read error, found {:?}", e),
+        }
+        assert_eq!(tokens.next(), None);
+    }
+
+    #[test]
+    fn lex_borrowed() {
+        let s = "select Name, 'it''s', 'plain', \"Q\" from users";
+        let borrowed: Vec<bool> = Lexer::tokens(s)
+            .filter_map(|tok| match tok.unwrap().token {
+                Token::Identifier(text)
+                | Token::QuotedIdentifier(text)
+                | Token::StringLiteral(text) => Some(matches!(text, Cow::Borrowed(_))),
+                _ => None,
+            })
+            .collect();
+        // Text is copied only when it is folded or unescaped
+        assert_eq!(borrowed, vec![false, false, true, true, true]);
+
+        // Nothing can be borrowed from a reader
+        assert!(Lexer::from_reader(s.as_bytes()).all(|tok| match tok.unwrap().token {
+            Token::Identifier(text) | Token::QuotedIdentifier(text) | Token::StringLiteral(text) => {
+                matches!(text, Cow::Owned(_))
+            }
+            _ => true,
+        }));
+    }
+
+    #[test]
+    fn lex_buffer() {
+        // Only escaped literals are copied into the buffer when the source
+        // is available
+        let s = "select ab, 12.5e3, .5, 0x1F, $12, :n, 'x''y''', `a\\`b`, \"q\"\"\" from t";
+        let mut lex = Lexer::new(Some(s));
+        let mut copied = Vec::new();
+        for c in s.chars() {
+            lex.feed(c).unwrap();
+            if !lex.buffer.is_empty() {
+                copied.push(c);
+            }
+        }
+        assert_eq!(copied.into_iter().collect::<String>(), "'y'''`b\"\"");
+
+        // Which leaves the text the same as lexing from a reader
+        let expected: Vec<LexerResult<SpannedToken>> = Lexer::tokens(s).collect();
+        assert_eq!(Lexer::from_reader(s.as_bytes()).collect::<Vec<_>>(), expected);
+        let literals: Vec<Token> = expected
+            .into_iter()
+            .map(|tok| tok.unwrap().token)
+            .filter(|tok| matches!(tok, Token::StringLiteral(_) | Token::QuotedIdentifier(_)))
+            .collect();
+        assert_eq!(
+            literals,
+            vec![
+                Token::StringLiteral("x'y'".into()),
+                Token::StringLiteral("a`b".into()),
+                Token::QuotedIdentifier("q\"".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn lex_placeholders() {
+        assert_eq!(
+            tokens("a=?and b=$12 or c=:Name_1;"),
+            vec![
+                Token::Identifier("a".into()),
+                Token::EQUAL,
+                Token::Placeholder(Placeholder::Anonymous),
+                Token::AND,
+                Token::Identifier("b".into()),
+                Token::EQUAL,
+                Token::Placeholder(Placeholder::Index(12)),
+                Token::OR,
+                Token::Identifier("c".into()),
+                Token::EQUAL,
+                Token::Placeholder(Placeholder::Named("Name_1".into())),
+                Token::SEMICOLON,
+            ]
+        );
+        match Lexer::lex("a = $ 1") {
+            Err(Error::IllegalCharacter { found, span, .. }) => {
+                assert_eq!(found, '$');
+                assert_eq!((span.start, span.end), (4, 5));
+            }
+            e => panic!("Expected illegal character, found {:?}", e.map(|_| ())),
+        }
+        match Lexer::lex("a = $0") {
+            Err(e) => assert_eq!(e, Error::InvalidPlaceholder {
+                literal: "$0".into(),
+                span: Span::new(4, 6, 1, 5),
+            }),
+            e => panic!("Expected invalid placeholder, found {:?}", e.map(|_| ())),
+        }
+        for &(s, literal) in &[("$1a", "$1a"), ("$12_b)", "$12_b")] {
+            match Lexer::lex(s) {
+                Err(Error::InvalidPlaceholder { literal: ref l, span }) => {
+                    assert_eq!(l, literal);
+                    assert_eq!(&s[span.start..span.end], literal);
+                }
+                e => panic!("Expected invalid placeholder, found {:?}", e.map(|_| ())),
+            }
+        }
+        match Lexer::lex("a = $99999999999999999999") {
+            Err(Error::NumberOverflow { .. }) => (),
+            e => panic!("Expected overflow, found {:?}", e.map(|_| ())),
+        }
+        assert!(Lexer::lex("a = :").is_err());
     }
 }
@@ -6,3 +6,376 @@ pub mod lexer;
 pub mod token;
 pub mod parser;
 pub mod ast;
+
+use std::io::Read;
+use self::ast::Syntax;
+use self::ast::statement::Statement;
+use self::lexer::Lexer;
+use self::parser::{Parser, ParserResult};
+use self::token::Token;
+use error::Error;
+
+/// Iterator that lazily parses a script of `;` separated statements,
+/// reading only as much input as is needed for the next statement
+///
+/// Empty statements are skipped, and the final statement does not need
+/// to be terminated by a semicolon. Iteration stops after the first error
+pub struct Statements<'a> {
+    parser: Parser<'a>,
+    failed: bool,
+}
+
+impl<'a> Statements<'a> {
+    pub fn new(parser: Parser<'a>) -> Statements<'a> {
+        Statements {
+            parser,
+            failed: false,
+        }
+    }
+}
+
+impl<'a> Iterator for Statements<'a> {
+    type Item = ParserResult<Statement<'a>>;
+
+    fn next(&mut self) -> Option<ParserResult<Statement<'a>>> {
+        if self.failed {
+            return None;
+        }
+        let parser = &mut self.parser;
+        while parser.pop_if(&Token::SEMICOLON) {}
+        // The terminating `;` is left in the queue, so that the lexer does
+        // not read into the next statement
+        let result = parser.peek().is_some().then(|| {
+            Statement::parse(parser).and_then(|statement| {
+                if parser.peek().is_none() || parser.peek_is(&Token::SEMICOLON) {
+                    Ok(statement)
+                } else {
+                    Err(parser.unexpected(&["`;`"]))
+                }
+            })
+        });
+        // Tokens were dropped where the lexer failed, so report that rather
+        // than whatever the parser made of what remained
+        let result = match parser.take_lexer_errors().into_iter().next() {
+            Some(e) => Some(Err(e)),
+            None => result,
+        };
+        self.failed = matches!(result, Some(Err(_)));
+        result
+    }
+}
+
+/// Lex and parse a script of `;` separated statements
+///
+/// Empty statements are skipped, and the final statement does not need
+/// to be terminated by a semicolon
+pub fn parse_script<'a>(s: &'a str) -> ParserResult<Vec<Statement<'a>>> {
+    Statements::new(Parser::new(Lexer::tokens(s))).collect()
+}
+
+/// Lazily parse a script of `;` separated statements from a reader, such
+/// as a SQL dump that is too large to hold in memory
+pub fn parse_reader<'a, R: Read + 'a>(reader: R) -> Statements<'a> {
+    Statements::new(Parser::new(Lexer::from_reader(reader)))
+}
+
+/// Rewrite a script in canonical form, with one statement per line.
+/// Comments are not preserved
+pub fn format_script(s: &str) -> ParserResult<String> {
+    let mut formatted = String::new();
+    for statement in parse_script(s)? {
+        formatted.push_str(&statement.to_string());
+        formatted.push_str(";\n");
+    }
+    Ok(formatted)
+}
+
+/// Lex and parse a script of `;` separated statements, recovering from
+/// errors so that every broken statement in the script is diagnosed
+///
+/// A statement that fails to parse is skipped up to the next `;`. Only
+/// statements that lexed and parsed without any error are returned, along
+/// with all of the errors in source order
+pub fn parse_script_recovering<'a>(s: &'a str) -> (Vec<Statement<'a>>, Vec<Error>) {
+    let mut parser = Parser::new(Lexer::tokens(s));
+    let mut errors = Vec::new();
+    let mut statements = Vec::new();
+    loop {
+        while parser.pop_if(&Token::SEMICOLON) {}
+        // Lexer errors between statements
+        errors.extend(parser.take_lexer_errors());
+        if parser.peek().is_none() {
+            break;
+        }
+
+        let result = Statement::parse(&mut parser).and_then(|statement| {
+            if parser.peek().is_none() || parser.peek_is(&Token::SEMICOLON) {
+                Ok(statement)
+            } else {
+                Err(parser.unexpected(&["`;`"]))
+            }
+        });
+        let mut statement_errors = parser.take_errors();
+        let statement = match result {
+            Ok(statement) => Some(statement),
+            Err(e) => {
+                statement_errors.push(e);
+                parser.synchronize(&[]);
+                None
+            }
+        };
+
+        // Tokens were dropped from statements containing lexer errors, so
+        // any parser errors are likely a consequence of those
+        let lexer_errors = parser.take_lexer_errors();
+        if !lexer_errors.is_empty() {
+            errors.extend(lexer_errors);
+            continue;
+        }
+        match statement {
+            Some(statement) if statement_errors.is_empty() => statements.push(statement),
+            _ => errors.extend(statement_errors),
+        }
+    }
+    errors.sort_by_key(|e| e.span().start);
+    (statements, errors)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::ast::columns::Column;
+    use super::ast::create::{ColumnDef, CreateTable};
+    use super::ast::drop::DropTable;
+    use super::ast::expr::Expr;
+    use super::ast::insert::Insert;
+    use super::ast::select::{OrderBy, Select};
+    use super::ast::visit::*;
+    use super::token::Span;
+
+    #[test]
+    fn script() {
+        let statements = parse_script(
+            "-- schema
+            create table users (id integer serial, name text not null);
+            insert into users (name) values (`alice`), (`bob`);;
+            select * from users where id > 1
+            ",
+        ).unwrap();
+        assert_eq!(statements.len(), 3);
+        match statements[0] {
+            Statement::CreateTable(_) => (),
+            _ => panic!("Expected CREATE TABLE"),
+        }
+        match statements[1] {
+            Statement::Insert(_) => (),
+            _ => panic!("Expected INSERT"),
+        }
+        match statements[2] {
+            Statement::Select(_) => (),
+            _ => panic!("Expected SELECT"),
+        }
+    }
+
+    #[test]
+    fn script_missing_semicolon() {
+        assert!(parse_script("drop table t1 drop table t2").is_err());
+        assert!(parse_script("").unwrap().is_empty());
+    }
+
+    #[test]
+    fn script_error_location() {
+        match parse_script("select *\n  from where") {
+            Err(Error::UnexpectedToken {
+                expected,
+                found,
+                span,
+            }) => {
+                assert_eq!(expected, vec!["identifier".to_string()]);
+                assert_eq!(found, Token::WHERE);
+                assert_eq!((span.line, span.column), (2, 8));
+                assert_eq!((span.start, span.end), (16, 21));
+            }
+            e => panic!("Expected error, found {:?}", e),
+        }
+        match parse_script("select * from") {
+            Err(Error::UnexpectedEof { span, .. }) => assert_eq!(span.start, 13),
+            e => panic!("Expected error, found {:?}", e),
+        }
+    }
+
+    #[test]
+    fn script_recovering() {
+        let (statements, errors) = parse_script_recovering(
+            "create table t (id integer, name);
+            select a + from t;
+            insert into t values (1, `a`);
+            insert into t values (1, +), (2, *);
+            drop table t # u;
+            drop table t",
+        );
+        assert_eq!(statements.len(), 2);
+        match statements[0] {
+            Statement::Insert(_) => (),
+            _ => panic!("Expected INSERT"),
+        }
+        match statements[1] {
+            Statement::DropTable(_) => (),
+            _ => panic!("Expected DROP TABLE"),
+        }
+
+        let lines: Vec<usize> = errors.iter().map(|e| e.span().line).collect();
+        assert_eq!(lines, vec![1, 2, 4, 4, 5]);
+        match errors[4] {
+            Error::IllegalCharacter { found, .. } => assert_eq!(found, '#'),
+            ref e => panic!("Expected illegal character, found {:?}", e),
+        }
+    }
+
+    #[test]
+    fn script_recovering_malformed_number() {
+        // The `;` ending the malformed number still ends the statement
+        let (statements, errors) = parse_script_recovering("select 1x; select a from t");
+        assert_eq!(statements.len(), 1);
+        assert_eq!(statements[0].to_string(), "SELECT a FROM t");
+        assert_eq!(errors.len(), 1);
+        match errors[0] {
+            Error::MalformedNumber { ref literal, .. } => assert_eq!(literal, "1x"),
+            ref e => panic!("Expected malformed number, found {:?}", e),
+        }
+    }
+
+    #[test]
+    fn script_recovering_placeholder() {
+        let (statements, errors) = parse_script_recovering("select $0;select a from t");
+        assert_eq!(statements.len(), 1);
+        assert_eq!(statements[0].to_string(), "SELECT a FROM t");
+        assert_eq!(errors.iter().map(Error::message).collect::<Vec<_>>(), vec![
+            "invalid placeholder `$0`",
+        ]);
+    }
+
+    #[test]
+    fn script_recovering_matches_strict() {
+        let script = "select * from t where; select * from u";
+        let (_, errors) = parse_script_recovering(script);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(Some(errors[0].clone()), parse_script(script).err());
+    }
+
+    #[test]
+    fn script_reader() {
+        use std::io::{self, Read};
+
+        let script = "create table t (a integer); insert into t values (1);";
+        let statements: Vec<Statement> = parse_reader(script.as_bytes())
+            .collect::<ParserResult<_>>()
+            .unwrap();
+        assert_eq!(statements.len(), 2);
+
+        // Statements are parsed as soon as they have been read, without
+        // waiting for the end of the input
+        let endless = "select a from t;".as_bytes().chain(io::repeat(b' '));
+        match parse_reader(endless).next() {
+            Some(Ok(Statement::Select(_))) => (),
+            s => panic!("Expected select, found {:?}", s),
+        }
+
+        // Iteration stops at the first error
+        let mut statements = parse_reader("select; select a from t".as_bytes());
+        assert!(matches!(statements.next(), Some(Err(_))));
+        assert!(statements.next().is_none());
+    }
+
+    /// Resets all spans, so that statements can be compared by structure
+    struct Strip;
+
+    impl<'a> VisitorMut<'a> for Strip {
+        fn visit_select_mut(&mut self, s: &mut Select<'a>) {
+            s.span = Span::default();
+            s.table.span = Span::default();
+            walk_select_mut(self, s);
+        }
+
+        fn visit_insert_mut(&mut self, s: &mut Insert<'a>) {
+            s.span = Span::default();
+            s.table.span = Span::default();
+            for c in &mut s.columns {
+                c.span = Span::default();
+            }
+            walk_insert_mut(self, s);
+        }
+
+        fn visit_create_table_mut(&mut self, s: &mut CreateTable<'a>) {
+            s.span = Span::default();
+            s.name.span = Span::default();
+            walk_create_table_mut(self, s);
+        }
+
+        fn visit_drop_table_mut(&mut self, s: &mut DropTable<'a>) {
+            s.span = Span::default();
+            for name in &mut s.names {
+                name.span = Span::default();
+            }
+        }
+
+        fn visit_column_mut(&mut self, column: &mut Column<'a>) {
+            match *column {
+                Column::All(ref mut span) | Column::AllFrom(_, ref mut span) => {
+                    *span = Span::default()
+                }
+                Column::Expr(..) => (),
+            }
+            walk_column_mut(self, column);
+        }
+
+        fn visit_order_by_mut(&mut self, order: &mut OrderBy<'a>) {
+            order.span = Span::default();
+            walk_order_by_mut(self, order);
+        }
+
+        fn visit_column_def_mut(&mut self, def: &mut ColumnDef<'a>) {
+            def.span = Span::default();
+            def.name.span = Span::default();
+            walk_column_def_mut(self, def);
+        }
+
+        fn visit_expr_mut(&mut self, expr: &mut Expr<'a>) {
+            expr.span = Span::default();
+            walk_expr_mut(self, expr);
+        }
+    }
+
+    fn parse_stripped<'a>(s: &'a str) -> Vec<Statement<'a>> {
+        let mut statements = parse_script(s).unwrap();
+        for statement in &mut statements {
+            Strip.visit_statement_mut(statement);
+        }
+        statements
+    }
+
+    #[test]
+    fn format_round_trip() {
+        let script = "
+            create table if not exists \"Users\" (
+                id int not null serial, name text default 'it''s' not null,
+                score float default -(1 + 2) * 3.5, avatar blob
+            );
+            insert into \"Users\" (id, name) values (1, `bob`), ($1, :name || ?);
+            insert into t values (null);
+            select * from t;
+            select t.id as user_id, u.*, a + 1 total from t;
+            select a, t.b, (a + b) * c, - -a from t where not (a = 1 or b <> 2) and c
+                order by a desc, b asc, c;
+            drop table if exists a, b;
+        ";
+        let formatted = format_script(script).unwrap();
+        assert_eq!(parse_stripped(&formatted), parse_stripped(script));
+        // Formatting is idempotent
+        assert_eq!(format_script(&formatted).unwrap(), formatted);
+        assert_eq!(
+            formatted.lines().nth(1),
+            Some("INSERT INTO \"Users\" (id, name) VALUES (1, 'bob'), ($1, :name || ?);")
+        );
+    }
+}
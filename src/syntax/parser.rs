@@ -1,36 +1,116 @@
-use std::collections::VecDeque;
-use super::token::Token;
+use std::mem;
+use error::Error;
+use super::token::{Span, SpannedToken, Token};
 
-pub type ParserResult<T> = Result<T, ParserError>;
+pub type ParserResult<T> = Result<T, Error>;
 
-#[derive(Debug)]
-pub enum ParserError {
-    Expecting(String),
-    OutOfTokens,
+/// Recursive descent parser over a stream of tokens. Tokens are pulled
+/// from the lexer one at a time as the parser advances
+pub struct Parser<'a> {
+    source: Box<dyn Iterator<Item = Result<SpannedToken<'a>, Error>> + 'a>,
+    // Next token in the stream, if there is one
+    next: Option<SpannedToken<'a>>,
+    // Location of the most recently popped token
+    last: Span,
+    // Empty span just past the most recently popped token
+    last_end: Span,
+    // Errors that parsing has recovered from
+    errors: Vec<Error>,
+    // Errors from the lexer. The offending input is skipped over
+    lexer_errors: Vec<Error>,
 }
 
-pub struct Parser {
-    tokens: VecDeque<Token>,
-}
-
-impl Parser {
+impl<'a> Parser<'a> {
     /// Return a reference to the next token in the queue
-    pub fn peek(&self) -> Option<&Token> {
-        self.tokens.get(0)
+    pub fn peek(&self) -> Option<&Token<'a>> {
+        self.next.as_ref().map(|tok| &tok.token)
+    }
+
+    /// Location of the next token in the queue. If the queue is empty,
+    /// this is an empty span just past the last token
+    pub fn peek_span(&self) -> Span {
+        match self.next {
+            Some(ref tok) => tok.span,
+            None => self.last_end,
+        }
+    }
+
+    /// Return a span beginning at `start` and ending with the most
+    /// recently popped token
+    pub fn span_from(&self, start: Span) -> Span {
+        start.extend_to(self.last.end)
+    }
+
+    /// Build an error for the next token, which matched none of `expected`
+    pub fn unexpected(&self, expected: &[&str]) -> Error {
+        let expected = expected.iter().map(|s| s.to_string()).collect();
+        match self.next {
+            Some(ref tok) => Error::UnexpectedToken {
+                expected,
+                found: tok.token.clone().into_owned(),
+                span: tok.span,
+            },
+            None => Error::UnexpectedEof {
+                expected,
+                span: self.peek_span(),
+            },
+        }
+    }
+
+    /// Record an error that the parser has recovered from
+    pub fn report(&mut self, error: Error) {
+        self.errors.push(error);
+    }
+
+    /// Take all of the errors recovered from so far
+    pub fn take_errors(&mut self) -> Vec<Error> {
+        mem::take(&mut self.errors)
+    }
+
+    /// Take all of the errors from the lexer so far. The lexer is always
+    /// one token ahead of the parser, so these cover the input up to and
+    /// including the next token
+    pub fn take_lexer_errors(&mut self) -> Vec<Error> {
+        mem::take(&mut self.lexer_errors)
+    }
+
+    /// Skip tokens until the next `;`, or until one of `stop` is found
+    /// outside of any parentheses opened while skipping. The token that
+    /// stopped synchronization is left in the queue
+    pub fn synchronize(&mut self, stop: &[Token]) {
+        let mut depth = 0;
+        while let Some(tok) = self.peek() {
+            match *tok {
+                Token::SEMICOLON => return,
+                Token::LEFTPAREN => depth += 1,
+                Token::RIGHTPAREN if depth > 0 => depth -= 1,
+                ref tok if depth == 0 && stop.contains(tok) => return,
+                _ => (),
+            }
+            self.pop().expect("Impossible error");
+        }
     }
 
     /// Is the next token equal to `expecting`
     pub fn peek_is(&self, expecting: &Token) -> bool {
-        match self.peek() {
-            Some(token) if token == expecting => true,
-            _ => false,
-        }
+        matches!(self.peek(), Some(token) if token == expecting)
     }
 
     /// Mandatory pop
     /// Pop the next token off the queue
-    pub fn pop(&mut self) -> ParserResult<Token> {
-        self.tokens.pop_front().ok_or(ParserError::OutOfTokens)
+    pub fn pop(&mut self) -> ParserResult<Token<'a>> {
+        match self.next.take() {
+            Some(tok) => {
+                self.last = tok.span;
+                self.last_end = tok.end;
+                self.advance();
+                Ok(tok.token)
+            }
+            None => Err(Error::UnexpectedEof {
+                expected: Vec::new(),
+                span: self.peek_span(),
+            }),
+        }
     }
 
     /// Optional pop
@@ -39,59 +119,131 @@ impl Parser {
     pub fn pop_if(&mut self, expecting: &Token) -> bool {
         let eq = self.peek_is(expecting);
         if eq {
-            self.tokens.pop_front().expect("Impossible error");
+            self.pop().expect("Impossible error");
             true
         } else {
             false
         }
     }
 
-    pub fn expect(&mut self, expecting: &Token) -> ParserResult<Token> {
-        let tok = self.pop()?;
-        // We know tok is a token at this point, since the previous line
-        // would've done an early return with ParserError::OutOfTokens otherwise
-        if &tok == expecting {
-            Ok(tok)
+    pub fn expect(&mut self, expecting: &Token) -> ParserResult<Token<'a>> {
+        if self.peek_is(expecting) {
+            self.pop()
         } else {
-            Err(ParserError::Expecting(
-                format!("{:?}, found {:?}", tok, expecting),
-            ))
+            Err(self.unexpected(&[&format!("`{}`", expecting)]))
+        }
+    }
+
+    pub fn expect_string(&mut self) -> ParserResult<Token<'a>> {
+        match self.peek() {
+            Some(&Token::StringLiteral(_)) => self.pop(),
+            _ => Err(self.unexpected(&["string"])),
+        }
+    }
+
+    pub fn expect_number(&mut self) -> ParserResult<Token<'a>> {
+        match self.peek() {
+            Some(&Token::IntegerLiteral(_)) | Some(&Token::FloatLiteral(_)) => self.pop(),
+            _ => Err(self.unexpected(&["number"])),
+        }
+    }
+
+    pub fn expect_identifier(&mut self) -> ParserResult<Token<'a>> {
+        match self.peek() {
+            Some(&Token::Identifier(_)) | Some(&Token::QuotedIdentifier(_)) => self.pop(),
+            _ => Err(self.unexpected(&["identifier"])),
         }
     }
 
-    pub fn expect_string(&mut self) -> ParserResult<Token> {
-        let tok = self.pop()?;
-        // We know tok is a token at this point, since the previous line
-        // would've done an early return with ParserError::OutOfTokens otherwise
-        match tok {
-            Token::StringLiteral(_) => Ok(tok),
-            _ => Err(ParserError::Expecting(format!("string, found {:?}", tok))),
+    pub fn expect_type(&mut self) -> ParserResult<Token<'a>> {
+        match self.peek() {
+            Some(&Token::INTEGER) | Some(&Token::TEXT) | Some(&Token::FLOAT) | Some(&Token::BLOB) => {
+                self.pop()
+            }
+            _ => Err(self.unexpected(&["type"])),
         }
     }
 
-    pub fn expect_number(&mut self) -> ParserResult<Token> {
-        let tok = self.pop()?;
-        // We know tok is a token at this point, since the previous line
-        // would've done an early return with ParserError::OutOfTokens otherwise
-        match tok {
-            Token::NumberLiteral(_) => Ok(tok),
-            _ => Err(ParserError::Expecting(format!("number, found {:?}", tok))),
+    /// Pull the next token from the lexer, setting aside any errors
+    fn advance(&mut self) {
+        while self.next.is_none() {
+            match self.source.next() {
+                Some(Ok(tok)) => self.next = Some(tok),
+                Some(Err(e)) => self.lexer_errors.push(e),
+                None => return,
+            }
+        }
+    }
+
+    /// Parse a stream of tokens, such as those from `Lexer::tokens`
+    pub fn new<I>(tokens: I) -> Parser<'a>
+    where
+        I: Iterator<Item = Result<SpannedToken<'a>, Error>> + 'a,
+    {
+        let mut parser = Parser {
+            source: Box::new(tokens),
+            next: None,
+            last: Span::default(),
+            last_end: Span::default(),
+            errors: Vec::new(),
+            lexer_errors: Vec::new(),
+        };
+        parser.advance();
+        parser
+    }
+
+    pub fn from_tokens(v: Vec<SpannedToken<'a>>) -> Parser<'a> {
+        Parser::new(v.into_iter().map(Ok))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::lexer::Lexer;
+
+    #[test]
+    fn expect() {
+        let mut parser = Lexer::lex("select where").unwrap();
+        parser.expect(&Token::SELECT).unwrap();
+        match parser.expect(&Token::FROM) {
+            Err(Error::UnexpectedToken {
+                expected, found, ..
+            }) => {
+                assert_eq!(expected, vec!["`FROM`".to_string()]);
+                assert_eq!(found, Token::WHERE);
+            }
+            e => panic!("Expected error, found {:?}", e),
+        }
+        // The mismatched token is left in the queue
+        parser.expect(&Token::WHERE).unwrap();
+        match parser.expect_identifier() {
+            Err(Error::UnexpectedEof { expected, span }) => {
+                assert_eq!(expected, vec!["identifier".to_string()]);
+                assert_eq!(span, Span::new(12, 12, 1, 13));
+            }
+            e => panic!("Expected error, found {:?}", e),
         }
     }
 
-    pub fn expect_identifier(&mut self) -> ParserResult<Token> {
-        let tok = self.pop()?;
-        // We know tok is a token at this point, since the previous line
-        // would've done an early return with ParserError::OutOfTokens otherwise
-        match tok {
-            Token::Identifier(_) => Ok(tok),
-            _ => Err(ParserError::Expecting(
-                format!("identifier, found {:?}", tok),
-            )),
+    #[test]
+    fn eof_span() {
+        // Columns count characters rather than bytes, and a token may span
+        // several lines
+        for &(s, line, column) in &[("select 'é'", 1, 11), ("select 'a\nbc' ", 2, 4)] {
+            let mut parser = Lexer::lex(s).unwrap();
+            while parser.pop().is_ok() {}
+            let end = s.trim_end().len();
+            assert_eq!(parser.peek_span(), Span::new(end, end, line, column));
         }
     }
 
-    pub fn from_tokens(v: Vec<Token>) -> Parser {
-        Parser { tokens: VecDeque::from(v) }
+    #[test]
+    fn synchronize() {
+        let mut parser = Lexer::lex("a (b, c) d, e; f").unwrap();
+        parser.synchronize(&[Token::COMMA]);
+        assert_eq!(parser.pop().unwrap(), Token::COMMA);
+        parser.synchronize(&[Token::COMMA]);
+        assert_eq!(parser.pop().unwrap(), Token::SEMICOLON);
     }
 }
@@ -1,15 +1,26 @@
 //! Lexical tokens
+//!
+//! Tokens borrow identifiers and literals from the source text wherever
+//! possible, and only own their text when it had to be unescaped or folded
+//! to lower case, or when it was read from a stream
+use std::borrow::Cow;
+use std::fmt;
 use self::Token::*;
 
 #[derive(Debug, Clone, PartialEq, PartialOrd)]
-pub enum Token {
+pub enum Token<'a> {
     // keywords
     SELECT,
     FROM,
     WHERE,
     ORDER,
+    BY,
+    ASC,
+    DESC,
+    AS,
     INSERT,
     INTO,
+    VALUES,
     CREATE,
     TABLE,
     DROP,
@@ -55,14 +66,105 @@ pub enum Token {
     DOUBLEPIPE,
 
     // literals
-    StringLiteral(String),
-    NumberLiteral(String),
-    Identifier(String),
+    StringLiteral(Cow<'a, str>),
+    /// Integer literal. The lexer never produces a negative value, except
+    /// for `i64::MIN` in place of `9223372036854775808`, which only fits in
+    /// 64 bits once negated. The parser accepts it only after a `-`
+    IntegerLiteral(i64),
+    FloatLiteral(f64),
+    /// Unquoted identifier, folded to lower case
+    Identifier(Cow<'a, str>),
+    /// Double quoted identifier, which preserves case and may be a keyword
+    QuotedIdentifier(Cow<'a, str>),
+    /// Parameter of a prepared statement
+    Placeholder(Placeholder<'a>),
 }
 
-impl Token {
+/// Parameter of a prepared statement, which is bound to a value before the
+/// statement is run
+#[derive(Debug, Clone, PartialEq, PartialOrd)]
+pub enum Placeholder<'a> {
+    /// `?`, numbered by its position among the other `?` placeholders
+    Anonymous,
+    /// `$1`, numbered from 1
+    Index(usize),
+    /// `:name`, where the name is case sensitive
+    Named(Cow<'a, str>),
+}
+
+impl<'a> Placeholder<'a> {
+    /// Copy any borrowed name, so that the placeholder no longer refers to
+    /// the source
+    pub fn into_owned(self) -> Placeholder<'static> {
+        match self {
+            Placeholder::Anonymous => Placeholder::Anonymous,
+            Placeholder::Index(i) => Placeholder::Index(i),
+            Placeholder::Named(name) => Placeholder::Named(Cow::Owned(name.into_owned())),
+        }
+    }
+}
+
+impl<'a> fmt::Display for Placeholder<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Placeholder::Anonymous => write!(f, "?"),
+            Placeholder::Index(i) => write!(f, "${}", i),
+            Placeholder::Named(ref name) => write!(f, ":{}", name),
+        }
+    }
+}
+
+/// Location of a token or syntax node in the source text
+///
+/// `start` and `end` are byte offsets, `line` and `column` are the 1-based
+/// position of the first character
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize, line: usize, column: usize) -> Span {
+        Span {
+            start,
+            end,
+            line,
+            column,
+        }
+    }
+
+    /// Return a span starting at `self`, and ending at byte offset `end`
+    pub fn extend_to(self, end: usize) -> Span {
+        Span { end, ..self }
+    }
+}
+
+/// A token, and where it was found
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpannedToken<'a> {
+    pub token: Token<'a>,
+    pub span: Span,
+    /// Empty span just past the token, where anything missing after it is
+    /// reported
+    pub end: Span,
+}
+
+impl<'a> Token<'a> {
+    /// The name an identifier refers to, for looking up tables and columns.
+    /// Unquoted identifiers have already been folded to lower case, so a
+    /// quoted identifier only matches an unquoted one if it is lower case
+    pub fn name(&self) -> Option<&str> {
+        match *self {
+            Identifier(ref s) | QuotedIdentifier(ref s) => Some(s),
+            _ => None,
+        }
+    }
+
     /// Match a character into a token
-    pub fn from_char(c: char) -> Option<Token> {
+    pub fn from_char(c: char) -> Option<Token<'a>> {
         Some(match c {
             '=' => EQUAL,
             '<' => LESSTHAN,
@@ -80,40 +182,174 @@ impl Token {
             '&' => AMPERSAND,
             '|' => PIPE,
             '/' => FORWARDSLASH,
+            '?' => Token::Placeholder(Placeholder::Anonymous),
             _ => return None,
         })
     }
 
     /// Match a word into either a keyword, or assume it is an identifier
-    pub fn from_str(s: &str) -> Token {
-        let word: String = s.chars().flat_map(|c| c.to_lowercase()).collect();
-        match word.as_ref() {
-            "select" => SELECT,
-            "from" => FROM,
-            "where" => WHERE,
-            "order" => ORDER,
-            "insert" => INSERT,
-            "into" => INTO,
-            "create" => CREATE,
-            "table" => TABLE,
-            "drop" => DROP,
-            "if" => IF,
-            "exists" => EXISTS,
-            "not" => NOT,
-            "null" => NULL,
-            "default" => DEFAULT,
-            "serial" => SERIAL,
-            "and" => AND,
-            "or" => OR,
-            "int" | "integer" => INTEGER,
-            "text" => TEXT,
-            "float" => FLOAT,
-            "blob" => BLOB,
-            _ => Identifier(word),
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_str(s: &'a str) -> Token<'a> {
+        Token::from_word(Cow::Borrowed(s))
+    }
+
+    /// Match a word into either a keyword, or assume it is an identifier.
+    /// The word is only copied if it has to be folded to lower case
+    pub fn from_word(word: Cow<'a, str>) -> Token<'a> {
+        if let Some(keyword) = keyword(&word) {
+            return keyword;
+        }
+        if word.chars().any(char::is_uppercase) {
+            Identifier(Cow::Owned(word.to_lowercase()))
+        } else {
+            Identifier(word)
+        }
+    }
+
+    /// Copy any borrowed text, so that the token no longer refers to the
+    /// source
+    pub fn into_owned(self) -> Token<'static> {
+        match self {
+            StringLiteral(s) => StringLiteral(Cow::Owned(s.into_owned())),
+            Identifier(s) => Identifier(Cow::Owned(s.into_owned())),
+            QuotedIdentifier(s) => QuotedIdentifier(Cow::Owned(s.into_owned())),
+            IntegerLiteral(i) => IntegerLiteral(i),
+            FloatLiteral(x) => FloatLiteral(x),
+            Token::Placeholder(p) => Token::Placeholder(p.into_owned()),
+            SELECT => SELECT,
+            FROM => FROM,
+            WHERE => WHERE,
+            ORDER => ORDER,
+            BY => BY,
+            ASC => ASC,
+            DESC => DESC,
+            AS => AS,
+            INSERT => INSERT,
+            INTO => INTO,
+            VALUES => VALUES,
+            CREATE => CREATE,
+            TABLE => TABLE,
+            DROP => DROP,
+            IF => IF,
+            EXISTS => EXISTS,
+            NOT => NOT,
+            NULL => NULL,
+            DEFAULT => DEFAULT,
+            SERIAL => SERIAL,
+            AND => AND,
+            OR => OR,
+            INTEGER => INTEGER,
+            TEXT => TEXT,
+            FLOAT => FLOAT,
+            BLOB => BLOB,
+            EQUAL => EQUAL,
+            NOTEQUAL => NOTEQUAL,
+            LESSTHAN => LESSTHAN,
+            LESSTHANOREQUAL => LESSTHANOREQUAL,
+            GREATERTHAN => GREATERTHAN,
+            GREATERTHANOREQUAL => GREATERTHANOREQUAL,
+            PLUS => PLUS,
+            MINUS => MINUS,
+            FORWARDSLASH => FORWARDSLASH,
+            LEFTPAREN => LEFTPAREN,
+            RIGHTPAREN => RIGHTPAREN,
+            LEFTBRACKET => LEFTBRACKET,
+            RIGHTBRACKET => RIGHTBRACKET,
+            DOT => DOT,
+            COMMA => COMMA,
+            SEMICOLON => SEMICOLON,
+            ASTERISK => ASTERISK,
+            AMPERSAND => AMPERSAND,
+            PIPE => PIPE,
+            DOUBLEPIPE => DOUBLEPIPE,
         }
     }
 }
 
+/// Match a word into a keyword, ignoring case
+fn keyword(word: &str) -> Option<Token<'static>> {
+    // No keyword is longer than this, so longer words need not be checked
+    let mut lower = [0u8; 8];
+    if word.len() > lower.len() {
+        return None;
+    }
+    for (l, b) in lower.iter_mut().zip(word.bytes()) {
+        *l = b.to_ascii_lowercase();
+    }
+    Some(match &lower[..word.len()] {
+        b"select" => SELECT,
+        b"from" => FROM,
+        b"where" => WHERE,
+        b"order" => ORDER,
+        b"by" => BY,
+        b"asc" => ASC,
+        b"desc" => DESC,
+        b"as" => AS,
+        b"insert" => INSERT,
+        b"into" => INTO,
+        b"values" => VALUES,
+        b"create" => CREATE,
+        b"table" => TABLE,
+        b"drop" => DROP,
+        b"if" => IF,
+        b"exists" => EXISTS,
+        b"not" => NOT,
+        b"null" => NULL,
+        b"default" => DEFAULT,
+        b"serial" => SERIAL,
+        b"and" => AND,
+        b"or" => OR,
+        b"int" | b"integer" => INTEGER,
+        b"text" => TEXT,
+        b"float" => FLOAT,
+        b"blob" => BLOB,
+        _ => return None,
+    })
+}
+
+/// Write a float so that it reads back as a float rather than an integer.
+/// Debug formatting always includes a decimal point or exponent
+pub fn write_float(f: &mut fmt::Formatter, x: f64) -> fmt::Result {
+    write!(f, "{:?}", x)
+}
+
+impl<'a> fmt::Display for Token<'a> {
+    /// Write the token as it would appear in source text
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = match *self {
+            EQUAL => "=",
+            NOTEQUAL => "<>",
+            LESSTHAN => "<",
+            LESSTHANOREQUAL => "<=",
+            GREATERTHAN => ">",
+            GREATERTHANOREQUAL => ">=",
+            PLUS => "+",
+            MINUS => "-",
+            FORWARDSLASH => "/",
+            LEFTPAREN => "(",
+            RIGHTPAREN => ")",
+            LEFTBRACKET => "[",
+            RIGHTBRACKET => "]",
+            DOT => ".",
+            COMMA => ",",
+            SEMICOLON => ";",
+            ASTERISK => "*",
+            AMPERSAND => "&",
+            PIPE => "|",
+            DOUBLEPIPE => "||",
+            StringLiteral(ref s) => return write!(f, "'{}'", s.replace('\'', "''")),
+            IntegerLiteral(i) => return write!(f, "{}", i),
+            FloatLiteral(x) => return write_float(f, x),
+            Identifier(ref s) => return write!(f, "{}", s),
+            QuotedIdentifier(ref s) => return write!(f, "\"{}\"", s.replace('"', "\"\"")),
+            Token::Placeholder(ref p) => return write!(f, "{}", p),
+            // Keywords and types are written the same as their variant name
+            ref keyword => return write!(f, "{:?}", keyword),
+        };
+        write!(f, "{}", s)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -125,6 +361,26 @@ mod tests {
         assert_eq!(Token::from_char('#'), None);
     }
 
+    #[test]
+    fn display() {
+        assert_eq!(Token::SELECT.to_string(), "SELECT");
+        assert_eq!(Token::GREATERTHANOREQUAL.to_string(), ">=");
+        assert_eq!(Token::IntegerLiteral(-3).to_string(), "-3");
+        assert_eq!(Token::FloatLiteral(2.0).to_string(), "2.0");
+        assert_eq!(Token::FloatLiteral(1e300).to_string(), "1e300");
+        assert_eq!(Token::StringLiteral("it's".into()).to_string(), "'it''s'");
+        assert_eq!(Token::Identifier("user_id".into()).to_string(), "user_id");
+        assert_eq!(Token::QuotedIdentifier("Order".into()).to_string(), "\"Order\"");
+    }
+
+    #[test]
+    fn name() {
+        assert_eq!(Token::Identifier("users".into()).name(), Some("users"));
+        assert_eq!(Token::QuotedIdentifier("Users".into()).name(), Some("Users"));
+        assert_eq!(Token::from_str("Users").name(), Some("users"));
+        assert_eq!(Token::SELECT.name(), None);
+    }
+
     #[test]
     fn from_str() {
         // Everything is case insensitive
@@ -136,4 +392,19 @@ mod tests {
             Token::Identifier("user_id".into())
         );
     }
+
+    #[test]
+    fn from_word() {
+        // Lower case identifiers are borrowed, others are folded into a copy
+        match Token::from_str("user_id") {
+            Identifier(Cow::Borrowed(s)) => assert_eq!(s, "user_id"),
+            t => panic!("Expected borrowed identifier, found {:?}", t),
+        }
+        match Token::from_str("UserId") {
+            Identifier(Cow::Owned(s)) => assert_eq!(s, "userid"),
+            t => panic!("Expected owned identifier, found {:?}", t),
+        }
+        assert_eq!(Token::from_str("integers"), Identifier("integers".into()));
+        assert_eq!(Token::from_str("Sélect"), Identifier("sélect".into()));
+    }
 }